@@ -21,8 +21,10 @@ fn main() {
                         xmltree::XMLNode::Element(element) => {
                             let restriction = element.attributes.get("base").unwrap();
                             let ty = xml_type_to_ident(restriction);
+                            // The field stays private so restricted values can't be built unchecked.
                             output.push(format!(
-                                "#[derive(Debug, serde::Deserialize)] pub struct {}({});",
+                                "#[derive(Debug, serde::Deserialize)] pub struct {0}({1}); \
+                                 impl {0} {{ pub fn get(&self) -> &{1} {{ &self.0 }} }}",
                                 name, ty
                             ))
                         }
@@ -63,24 +65,52 @@ fn main() {
                             });
 
                             let conversions = field_data.iter().filter_map(|(_, name, ty)| {
-                                if name == "Error"
-                                    || name == "RandomSongs"
-                                    || name == "SongsByGenre"
-                                {
+                                if name == "Error" {
                                     // error conversion is handled automatically
-                                    // RandomSongs and SongsByGenre both supertype `Songs` and so
-                                    // can't be automatically converted by sub type
                                     return None;
                                 }
+                                let is_shared = field_data
+                                    .iter()
+                                    .filter(|(_, _, other)| other == ty)
+                                    .count()
+                                    > 1;
+                                if is_shared {
+                                    // e.g. RandomSongs and SongsByGenre both supertype `Songs` and
+                                    // so can't be converted by sub type. Wrap them in a type named
+                                    // after the response element instead.
+                                    return Some(quote! {
+                                        #[derive(Debug)]
+                                        pub struct #name(pub #ty);
+
+                                        impl std::ops::Deref for #name {
+                                            type Target = #ty;
+
+                                            fn deref(&self) -> &Self::Target {
+                                                &self.0
+                                            }
+                                        }
+
+                                        impl TryFrom<Response> for #name {
+                                            type Error = crate::Error;
+
+                                            fn try_from(response: Response) -> Result<Self, Self::Error> {
+                                                match response {
+                                                    Response::#name(inner) => Ok(#name(inner)),
+                                                    response => Err(response.into()),
+                                                }
+                                            }
+                                        }
+                                    });
+                                }
                                 Some(quote! {
-                                    impl From<SubsonicResponse> for crate::SubsonicResponse<#ty> {
-                                        fn from(response: SubsonicResponse) -> Self {
-                                            let version = response.subsonic_response.version;
-                                            let result = match response.subsonic_response.content {
+                                    impl TryFrom<Response> for #ty {
+                                        type Error = crate::Error;
+
+                                        fn try_from(response: Response) -> Result<Self, Self::Error> {
+                                            match response {
                                                 Response::#name(inner) => Ok(inner),
-                                                _ => Err(response.subsonic_response.content.into()),
-                                            };
-                                            Self { version, result }
+                                                response => Err(response.into()),
+                                            }
                                         }
                                     }
                                 })
@@ -89,6 +119,7 @@ fn main() {
                             output.push(
                                 quote! {
                                     #[derive(Debug, serde::Deserialize)]
+                                    #[allow(clippy::large_enum_variant)]
                                     pub enum Response {
                                         #(#fields),*
                                     }
@@ -100,7 +131,10 @@ fn main() {
                             continue;
                         }
 
-                        let fields = element
+                        let is_mixed =
+                            element.attributes.get("mixed").map(String::as_str) == Some("true");
+
+                        let mut fields = element
                             .children
                             .into_iter()
                             .filter_map(only_elements)
@@ -121,7 +155,7 @@ fn main() {
                                         is_required: true,
                                         is_vec: false,
                                     }
-                                    .to_token_stream();
+                                    .into_token_stream();
 
                                     let mut fields = vec![quote! {
                                         #[serde(flatten)]
@@ -138,7 +172,15 @@ fn main() {
                                 _ => {
                                     unimplemented!("{}", element.name);
                                 }
+                            })
+                            .collect::<Vec<_>>();
+                        if is_mixed {
+                            // text content, e.g. the lyrics themselves or a genre's name
+                            fields.push(quote! {
+                                #[serde(default)]
+                                pub value: String
                             });
+                        }
 
                         let name = format_ident!("{}", name);
                         output.push(
@@ -224,7 +266,7 @@ fn gen_field(element: xmltree::Element) -> proc_macro2::TokenStream {
             is_vec,
         };
 
-        config.to_token_stream()
+        config.into_token_stream()
     }
 
     if element.name == "sequence" {
@@ -272,7 +314,7 @@ struct GenNamedFieldConfig<'a> {
 }
 
 impl GenNamedFieldConfig<'_> {
-    fn to_token_stream(self) -> proc_macro2::TokenStream {
+    fn into_token_stream(self) -> proc_macro2::TokenStream {
         let ty = xml_type_to_ident(self.ty).to_token_stream();
         let ty = if self.is_vec { quote!(Vec<#ty>) } else { ty };
         let ty = if !self.is_required {
@@ -23,6 +23,10 @@ pub struct Client {
     auth: Auth,
     inner: reqwest::Client,
     version: semver::Version,
+    server_version: std::sync::Mutex<Option<String>>,
+    client_name: String,
+    timeout: Option<std::time::Duration>,
+    user_agent: Option<String>,
 }
 
 impl std::fmt::Debug for Client {
@@ -31,69 +35,573 @@ impl std::fmt::Debug for Client {
             .field("base_url", &self.base_url)
             .field("auth", &self.auth)
             .field("version", &self.version)
+            .field("client_name", &self.client_name)
             .finish()
     }
 }
 
+/// Configures a `Client`, e.g. to share a connection pool or to identify the application to the
+/// server.
+#[derive(Debug)]
+pub struct ClientBuilder {
+    base_url: Result<reqwest::Url>,
+    auth: Auth,
+    inner: Option<reqwest::Client>,
+    version: Option<semver::Version>,
+    client_name: String,
+    timeout: Option<std::time::Duration>,
+    user_agent: Option<String>,
+}
+
+impl ClientBuilder {
+    pub fn new<U: reqwest::IntoUrl>(base_url: U, user: String, password: String) -> Self {
+        let base_url = base_url
+            .into_url()
+            .map_err(|error| Error::InvalidInput(format!("invalid base URL: {}", error)));
+        Self {
+            base_url,
+            auth: Auth::new(user, password),
+            inner: None,
+            version: None,
+            client_name: env!("CARGO_PKG_NAME").to_owned(),
+            timeout: None,
+            user_agent: None,
+        }
+    }
+
+    /// Sends requests through an existing client, e.g. one set up with a proxy or custom TLS
+    /// roots.
+    pub fn http_client(self, inner: reqwest::Client) -> Self {
+        Self {
+            inner: Some(inner),
+            ..self
+        }
+    }
+
+    /// Applies to each request, from sending it until its body has been read.
+    pub fn timeout(self, timeout: std::time::Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Identifies the application to the server (the `c` parameter). Defaults to the name of this
+    /// crate.
+    pub fn client_name(self, client_name: impl Into<String>) -> Self {
+        Self {
+            client_name: client_name.into(),
+            ..self
+        }
+    }
+
+    pub fn user_agent(self, user_agent: impl Into<String>) -> Self {
+        Self {
+            user_agent: Some(user_agent.into()),
+            ..self
+        }
+    }
+
+    /// Defaults to token authentication where the API version supports it, falling back to the
+    /// encoded password if the server refuses tokens, and to the plain text password otherwise.
+    pub fn auth_method(self, method: AuthMethod) -> Self {
+        Self {
+            auth: Auth {
+                method: Some(method),
+                ..self.auth
+            },
+            ..self
+        }
+    }
+
+    /// Defaults to `AuthStrategy::PerRequest`. Reusing the parameters saves hashing on every
+    /// request, at the cost of replayable URLs.
+    pub fn auth_strategy(self, strategy: AuthStrategy) -> Self {
+        Self {
+            auth: Auth {
+                strategy,
+                ..self.auth
+            },
+            ..self
+        }
+    }
+
+    /// Uses this API version instead of negotiating one in `connect`.
+    pub fn version(self, version: semver::Version) -> Self {
+        Self {
+            version: Some(version),
+            ..self
+        }
+    }
+
+    /// Builds the client without contacting the server. Unless a version was forced, it assumes
+    /// `Client::API_VERSION`.
+    pub fn build(self) -> Result<Client> {
+        Ok(Client {
+            base_url: self.base_url?,
+            auth: self.auth,
+            inner: self.inner.unwrap_or_default(),
+            version: self.version.unwrap_or(Client::API_VERSION),
+            server_version: Default::default(),
+            client_name: self.client_name,
+            timeout: self.timeout,
+            user_agent: self.user_agent,
+        })
+    }
+
+    /// Builds the client and pings the server to settle on the newest API version both sides
+    /// support, unless a version was forced.
+    pub async fn connect(self) -> Result<Client> {
+        let forced = self.version.is_some();
+        let mut client = self.build()?;
+        // Servers report their version even when rejecting the request, e.g. because they don't
+        // understand token authentication yet.
+        let pinged = client.ping().await;
+        let server_version = match client.server_version() {
+            Some(server_version) if !forced => server_version,
+            _ => return pinged.map(|()| client),
+        };
+        let version = Client::negotiate(&server_version)?;
+        if version == client.version {
+            pinged?;
+        } else {
+            client.version = version;
+            client.auth.invalidate();
+            client.ping().await?;
+        }
+        Ok(client)
+    }
+}
+
 impl Client {
-    pub fn new<U: reqwest::IntoUrl>(
+    /// The newest API version this crate speaks.
+    pub const API_VERSION: semver::Version = semver::Version::new(1, 16, 1);
+
+    /// Creates a client that assumes the server supports `API_VERSION`. Use `connect` to find out.
+    pub fn new<U: reqwest::IntoUrl>(base_url: U, user: String, password: String) -> Result<Self> {
+        Self::builder(base_url, user, password).build()
+    }
+
+    /// Creates a client and pings the server to settle on the newest API version both sides
+    /// support. That version is sent with every request and decides how to authenticate.
+    pub async fn connect<U: reqwest::IntoUrl>(
         base_url: U,
         user: String,
         password: String,
-    ) -> std::result::Result<Self, Box<dyn std::error::Error>> {
-        let base_url = base_url.into_url()?;
-        let auth = Auth { user, password };
-        // TODO: pull from API
-        let version = semver::Version::parse("1.16.1")?;
-        Ok(Self {
-            base_url,
-            auth,
-            inner: reqwest::Client::new(),
-            version,
-        })
+    ) -> Result<Self> {
+        Self::builder(base_url, user, password).connect().await
     }
 
-    pub async fn ping(&self) -> Result<bool> {
-        let response = self
-            .get("ping")
-            .send()
-            .await?
-            .json::<api::GenericSubsonicResponse<()>>()
-            .await?;
-        Ok(response.subsonic_response.status == "ok")
+    pub fn builder<U: reqwest::IntoUrl>(
+        base_url: U,
+        user: String,
+        password: String,
+    ) -> ClientBuilder {
+        ClientBuilder::new(base_url, user, password)
     }
 
-    fn get(&self, query: &str) -> reqwest::RequestBuilder {
-        let mut url = self
-            .base_url
-            .join("rest/")
-            .and_then(|url| url.join(query))
-            .unwrap();
-        url.query_pairs_mut()
-            .extend_pairs(self.auth.to_query(&self.version));
-        self.inner.get(url)
+    fn negotiate(server_version: &str) -> Result<semver::Version> {
+        let server_version = semver::Version::parse(server_version).map_err(|error| {
+            Error::InvalidInput(format!(
+                "invalid server version {:?}: {}",
+                server_version, error
+            ))
+        })?;
+        Ok(server_version.min(Self::API_VERSION))
     }
 
-    fn get_with_params<P: serde::Serialize>(
-        &self,
-        query: &str,
-        params: &P,
-    ) -> reqwest::RequestBuilder {
-        let mut url = self
+    /// The name sent to identify the application to the server.
+    pub fn client_name(&self) -> &str {
+        &self.client_name
+    }
+
+    /// The API version used for requests.
+    pub fn version(&self) -> &semver::Version {
+        &self.version
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        self.get("ping").send_empty().await
+    }
+
+    /// The API version reported by the server in its most recent response.
+    pub fn server_version(&self) -> Option<String> {
+        self.server_version.lock().unwrap().clone()
+    }
+
+    fn get(&self, query: &str) -> RequestBuilder<'_> {
+        let url = self
             .base_url
             .join("rest/")
             .and_then(|url| url.join(query))
             .unwrap();
-        url.query_pairs_mut()
-            .extend_pairs(self.auth.to_query(&self.version))
-            .append_key_only(&serde_qs::to_string(params).unwrap());
-        self.inner.get(url)
+        let mut inner = self.inner.get(url);
+        if let Some(timeout) = self.timeout {
+            inner = inner.timeout(timeout);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            inner = inner.header(reqwest::header::USER_AGENT, user_agent);
+        }
+        let required = Self::required_version(query);
+        RequestBuilder {
+            client: self,
+            inner,
+            unsupported: (required > self.version).then_some(required),
+        }
+    }
+
+    /// The API version that introduced an endpoint.
+    fn required_version(endpoint: &str) -> semver::Version {
+        let (minor, patch) = match endpoint {
+            "createUser" | "changePassword" => (1, 0),
+            "getAlbumList" | "getRandomSongs" | "getLyrics" | "jukeboxControl"
+            | "getChatMessages" | "addChatMessage" | "createPlaylist" | "deletePlaylist" => (2, 0),
+            "getUser" | "deleteUser" => (3, 0),
+            "search2" => (4, 0),
+            "scrobble" => (5, 0),
+            "setRating" | "getPodcasts" | "getShares" | "createShare" | "updateShare"
+            | "deleteShare" => (6, 0),
+            "getArtists" | "getArtist" | "getAlbum" | "getSong" | "getVideos" | "getAlbumList2"
+            | "getStarred" | "getStarred2" | "search3" | "updatePlaylist" | "hls.m3u8"
+            | "getAvatar" | "star" | "unstar" | "getUsers" => (8, 0),
+            "getSongsByGenre"
+            | "getGenres"
+            | "refreshPodcasts"
+            | "createPodcastChannel"
+            | "deletePodcastChannel"
+            | "deletePodcastEpisode"
+            | "downloadPodcastEpisode"
+            | "getInternetRadioStations"
+            | "getBookmarks"
+            | "createBookmark"
+            | "deleteBookmark" => (9, 0),
+            "updateUser" => (10, 1),
+            "getArtistInfo" | "getArtistInfo2" | "getSimilarSongs" | "getSimilarSongs2" => (11, 0),
+            "getPlayQueue" | "savePlayQueue" => (12, 0),
+            "getTopSongs" | "getNewestPodcasts" => (13, 0),
+            "getAlbumInfo" | "getAlbumInfo2" | "getVideoInfo" | "getCaptions" => (14, 0),
+            "getScanStatus" | "startScan" => (15, 0),
+            "createInternetRadioStation"
+            | "updateInternetRadioStation"
+            | "deleteInternetRadioStation" => (16, 0),
+            _ => (0, 0),
+        };
+        semver::Version::new(1, minor, patch)
+    }
+
+    fn get_with_params<P: serde::Serialize>(&self, query: &str, params: &P) -> RequestBuilder<'_> {
+        self.get(query).query(&query_pairs(params))
+    }
+}
+
+/// Wraps `reqwest::RequestBuilder` to decode Subsonic responses into `Error`s.
+struct RequestBuilder<'a> {
+    client: &'a Client,
+    inner: reqwest::RequestBuilder,
+    /// Set when the endpoint is newer than the negotiated API version, to refuse sending it.
+    unsupported: Option<semver::Version>,
+}
+
+impl RequestBuilder<'_> {
+    /// How much of an undecodable body to keep in `Error::Decode`.
+    const BODY_SNIPPET_SIZE: usize = 256;
+
+    fn query<Q: serde::Serialize + ?Sized>(self, query: &Q) -> Self {
+        Self {
+            inner: self.inner.query(query),
+            ..self
+        }
+    }
+
+    fn check_version(&self) -> Result<()> {
+        match &self.unsupported {
+            Some(required) => Err(Error::UnsupportedVersion {
+                required: required.clone(),
+                version: self.client.version.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The request without authentication, which is only added when sending it.
+    fn build_unauthenticated(self) -> Result<reqwest::Request> {
+        self.check_version()?;
+        Ok(self.inner.build()?)
+    }
+
+    fn build(self) -> Result<reqwest::Request> {
+        let client = self.client;
+        let mut request = self.build_unauthenticated()?;
+        client.authenticate(&mut request);
+        Ok(request)
+    }
+
+    /// Sends the request and hands the response to `read`. If the server refuses token
+    /// authentication, retries once with the method it falls back to.
+    async fn send_with<T, F, Fut>(self, read: F) -> Result<T>
+    where
+        F: Fn(reqwest::Response) -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let client = self.client;
+        let request = self.build_unauthenticated()?;
+        // GET requests have no body, so they can always be cloned.
+        let retry = request.try_clone();
+        let (response, sent_with_token) = client.execute(request).await?;
+        match read(response).await {
+            // Decide by how this request was sent, as a concurrent one may have fallen back already.
+            Err(error)
+                if error.code() == Some(ErrorCode::TokenAuthNotSupported)
+                    && sent_with_token
+                    && client.auth.fall_back(&client.version) =>
+            {
+                match retry {
+                    Some(request) => read(client.execute(request).await?.0).await,
+                    None => Err(error),
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// For endpoints that return a file. Failures are still reported as a regular response, which
+    /// is turned into an error.
+    async fn execute(self) -> Result<reqwest::Response> {
+        let client = self.client;
+        self.send_with(|response| client.check_file(response)).await
+    }
+
+    async fn send<T: TryFrom<api::Response, Error = Error>>(self) -> Result<T> {
+        match self.send_content().await? {
+            Some(content) => content.try_into(),
+            None => Err(Error::UnexpectedResponse(None)),
+        }
+    }
+
+    /// For responses that may not carry any content, such as the acknowledgement of a write.
+    async fn send_optional<T: TryFrom<api::Response, Error = Error>>(self) -> Result<Option<T>> {
+        self.send_content()
+            .await?
+            .map(TryInto::try_into)
+            .transpose()
+    }
+
+    async fn send_empty(self) -> Result<()> {
+        match self.send_content().await? {
+            Some(content) => Err(content.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads a whole file along with its content type.
+    async fn send_binary(self) -> Result<(Option<String>, bytes::Bytes)> {
+        let response = self.execute().await?;
+        let content_type = content_type(&response).map(str::to_owned);
+        Ok((content_type, response.bytes().await?))
+    }
+
+    /// Errors reported by the server are returned as `Error::Api`.
+    async fn send_content(self) -> Result<Option<api::Response>> {
+        let client = self.client;
+        self.send_with(|response| async move {
+            match client.decode(&response.bytes().await?)? {
+                Some(api::Response::Error(error)) => Err(Error::Api(error)),
+                content => Ok(content),
+            }
+        })
+        .await
+    }
+}
+
+fn content_type(response: &reqwest::Response) -> Option<&str> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+}
+
+impl Client {
+    /// Returns whether the request was authenticated with a token.
+    fn authenticate(&self, request: &mut reqwest::Request) -> bool {
+        let (auth, token) = self.auth.query(&self.version, &self.client_name);
+        let url = request.url_mut();
+        match url.query().filter(|query| !query.is_empty()) {
+            Some(query) => {
+                let query = format!("{}&{}", query, auth);
+                url.set_query(Some(&query));
+            }
+            None => url.set_query(Some(&auth)),
+        }
+        token
+    }
+
+    /// Also returns whether the request was authenticated with a token.
+    async fn execute(&self, mut request: reqwest::Request) -> Result<(reqwest::Response, bool)> {
+        let token = self.authenticate(&mut request);
+        let response = self.inner.execute(request).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Status(status));
+        }
+        Ok((response, token))
+    }
+
+    /// Passes a file through, unless the server answered with a JSON response instead.
+    async fn check_file(&self, response: reqwest::Response) -> Result<reqwest::Response> {
+        let is_json = content_type(&response)
+            .and_then(|content_type| content_type.split(';').next())
+            == Some("application/json");
+        if !is_json {
+            return Ok(response);
+        }
+        Err(match self.decode(&response.bytes().await?)? {
+            Some(content) => content.into(),
+            None => Error::UnexpectedResponse(None),
+        })
+    }
+
+    /// The way requests are currently authenticated.
+    pub fn auth_method(&self) -> AuthMethod {
+        self.auth.method(&self.version).into_owned()
+    }
+
+    fn decode(&self, body: &[u8]) -> Result<Option<api::Response>> {
+        // Try the strict form first so a response that doesn't match its type is reported as such
+        // rather than as missing.
+        let (version, content) = match serde_json::from_slice::<api::SubsonicResponse>(body) {
+            Ok(response) => (
+                response.subsonic_response.version,
+                Some(response.subsonic_response.content),
+            ),
+            Err(source) => {
+                // Flattening into an `Option` would turn a malformed payload into `None`, so look
+                // at the keys instead: only the response's own metadata means there's no content.
+                let metadata = ["type", "serverVersion", "openSubsonic"];
+                match serde_json::from_slice::<
+                    api::GenericSubsonicResponse<serde_json::Map<String, serde_json::Value>>,
+                >(body)
+                {
+                    Ok(response)
+                        if response
+                            .subsonic_response
+                            .content
+                            .keys()
+                            .all(|key| metadata.contains(&key.as_str())) =>
+                    {
+                        (response.subsonic_response.version, None)
+                    }
+                    _ => {
+                        let body = String::from_utf8_lossy(body)
+                            .chars()
+                            .take(RequestBuilder::BODY_SNIPPET_SIZE)
+                            .collect();
+                        return Err(Error::Decode { source, body });
+                    }
+                }
+            }
+        };
+        *self.server_version.lock().unwrap() = Some(version);
+        Ok(content)
+    }
+}
+
+/// Subsonic timestamps are milliseconds since the epoch.
+fn unix_millis(time: std::time::SystemTime) -> u64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn serialize_optional_time<S: serde::Serializer>(
+    time: &Option<std::time::SystemTime>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serializer.serialize_some(&unix_millis(*time)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Flattens `params` into query pairs. Sequences become repeated keys, which is how Subsonic
+/// expects multi-valued parameters like `songId`, and `None` values are omitted.
+fn query_pairs<P: serde::Serialize>(params: &P) -> Vec<(String, String)> {
+    fn to_query_value(value: serde_json::Value) -> Option<String> {
+        match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::Bool(value) => Some(value.to_string()),
+            serde_json::Value::Number(value) => Some(value.to_string()),
+            serde_json::Value::String(value) => Some(value),
+            value => panic!("unsupported query parameter value: {}", value),
+        }
+    }
+
+    let mut pairs = vec![];
+    match serde_json::to_value(params).unwrap() {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    serde_json::Value::Array(values) => pairs.extend(
+                        values
+                            .into_iter()
+                            .filter_map(to_query_value)
+                            .map(|value| (key.clone(), value)),
+                    ),
+                    value => pairs.extend(to_query_value(value).map(|value| (key, value))),
+                }
+            }
+        }
+        value => panic!("query parameters must be a struct or map: {}", value),
+    }
+    pairs
+}
+
+/// How requests are authenticated.
+#[derive(Clone, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// `t` and `s`: an MD5 token of the password and a random salt. Requires API version 1.13.0.
+    Token,
+    /// `p`: the password in plain text.
+    Password,
+    /// `p=enc:`: the hex-encoded password, which keeps it out of plain sight in server logs.
+    EncodedPassword,
+    /// `apiKey`: an OpenSubsonic API key, sent instead of the user name and password.
+    ApiKey(String),
+}
+
+impl std::fmt::Debug for AuthMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AuthMethod::Token => f.write_str("Token"),
+            AuthMethod::Password => f.write_str("Password"),
+            AuthMethod::EncodedPassword => f.write_str("EncodedPassword"),
+            AuthMethod::ApiKey(_) => f.debug_tuple("ApiKey").field(&"<secret>").finish(),
+        }
     }
 }
 
+/// When to compute the authentication parameters of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuthStrategy {
+    /// A new salt and token for every request.
+    #[default]
+    PerRequest,
+    /// Reuses the same salt and token for the given time.
+    Session(std::time::Duration),
+    /// Computes the parameters once and appends them to every request as they are.
+    Precomputed,
+}
+
 struct Auth {
     user: String,
     password: String,
+    /// Chosen by the user. Otherwise it's picked from the API version.
+    method: Option<AuthMethod>,
+    /// Set once the server answered error 41, e.g. for LDAP users.
+    token_unsupported: std::sync::atomic::AtomicBool,
+    strategy: AuthStrategy,
+    /// The encoded query, when it was computed and whether it holds a token, unless computed per
+    /// request.
+    cached: std::sync::Mutex<Option<(std::time::Instant, bool, std::sync::Arc<str>)>>,
 }
 
 impl std::fmt::Debug for Auth {
@@ -101,6 +609,8 @@ impl std::fmt::Debug for Auth {
         f.debug_struct("Auth")
             .field("user", &self.user)
             .field("password", &"<secret>")
+            .field("method", &self.method)
+            .field("strategy", &self.strategy)
             .finish()
     }
 }
@@ -108,8 +618,59 @@ impl std::fmt::Debug for Auth {
 impl Auth {
     const SALT_SIZE: usize = 36; // Minimum 6 characters.
 
-    fn to_query(&self, version: &semver::Version) -> impl Iterator<Item = (&'static str, String)> {
-        let mut pairs = Vec::with_capacity(6);
+    fn new(user: String, password: String) -> Self {
+        Self {
+            user,
+            password,
+            method: None,
+            token_unsupported: Default::default(),
+            strategy: Default::default(),
+            cached: Default::default(),
+        }
+    }
+
+    /// The encoded query string to append to requests, and whether it holds a token.
+    fn query(&self, version: &semver::Version, client_name: &str) -> (std::sync::Arc<str>, bool) {
+        let method = self.method(version);
+        let token = *method == AuthMethod::Token;
+        let encode = || {
+            url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(self.to_query(&method, version, client_name))
+                .finish()
+                .into()
+        };
+        let lifetime = match self.strategy {
+            AuthStrategy::PerRequest => return (encode(), token),
+            AuthStrategy::Session(lifetime) => Some(lifetime),
+            AuthStrategy::Precomputed => None,
+        };
+        let mut cached = self.cached.lock().unwrap();
+        match &*cached {
+            Some((created, cached_token, query))
+                if *cached_token == token
+                    && lifetime.is_none_or(|lifetime| created.elapsed() < lifetime) =>
+            {
+                (query.clone(), token)
+            }
+            _ => {
+                let query: std::sync::Arc<str> = encode();
+                *cached = Some((std::time::Instant::now(), token, query.clone()));
+                (query, token)
+            }
+        }
+    }
+
+    /// Forgets the cached query, e.g. because the API version or method changed.
+    fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+
+    fn method(&self, version: &semver::Version) -> std::borrow::Cow<'_, AuthMethod> {
+        use std::sync::atomic::Ordering;
+
+        if let Some(method) = &self.method {
+            return std::borrow::Cow::Borrowed(method);
+        }
         let good_auth_version = semver::Comparator {
             op: semver::Op::GreaterEq,
             major: 1,
@@ -117,88 +678,273 @@ impl Auth {
             patch: None,
             pre: Default::default(),
         };
-        if good_auth_version.matches(&version) {
-            use rand::{distributions::Alphanumeric, Rng};
+        if !good_auth_version.matches(version) {
+            std::borrow::Cow::Owned(AuthMethod::Password)
+        } else if self.token_unsupported.load(Ordering::Relaxed) {
+            // Keeps the password out of plain sight in the logs of servers that refuse tokens.
+            std::borrow::Cow::Owned(AuthMethod::EncodedPassword)
+        } else {
+            std::borrow::Cow::Owned(AuthMethod::Token)
+        }
+    }
 
-            let salt: String = rand::thread_rng()
-                .sample_iter(&Alphanumeric)
-                .take(Self::SALT_SIZE)
-                .map(char::from)
-                .collect();
-            let pre_t = self.password.to_string() + &salt;
-            let token = format!("{:x}", md5::compute(pre_t.as_bytes()));
+    /// Switches from token to encoded password authentication after the server refused tokens,
+    /// unless the method was chosen explicitly. Returns whether requests are no longer sent with
+    /// tokens, whether this call or an earlier one made the switch.
+    fn fall_back(&self, version: &semver::Version) -> bool {
+        use std::sync::atomic::Ordering;
 
-            pairs.push(("u", self.user.clone()));
-            pairs.push(("t", token));
-            pairs.push(("s", salt));
-        } else {
-            pairs.push(("u", self.user.clone()));
-            pairs.push(("p", self.password.clone()));
-        };
+        if self.method.is_some() {
+            return false;
+        }
+        if !self.token_unsupported.swap(true, Ordering::Relaxed) {
+            self.invalidate();
+        }
+        *self.method(version) != AuthMethod::Token
+    }
+
+    fn to_query(
+        &self,
+        method: &AuthMethod,
+        version: &semver::Version,
+        client_name: &str,
+    ) -> impl Iterator<Item = (&'static str, String)> {
+        let mut pairs = Vec::with_capacity(6);
+        match method {
+            AuthMethod::Token => {
+                use rand::{distributions::Alphanumeric, Rng};
+
+                let salt: String = rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(Self::SALT_SIZE)
+                    .map(char::from)
+                    .collect();
+                let pre_t = self.password.to_string() + &salt;
+                let token = format!("{:x}", md5::compute(pre_t.as_bytes()));
+
+                pairs.push(("u", self.user.clone()));
+                pairs.push(("t", token));
+                pairs.push(("s", salt));
+            }
+            AuthMethod::Password => {
+                pairs.push(("u", self.user.clone()));
+                pairs.push(("p", self.password.clone()));
+            }
+            AuthMethod::EncodedPassword => {
+                pairs.push(("u", self.user.clone()));
+                pairs.push(("p", EncodedPassword(&self.password).to_string()));
+            }
+            AuthMethod::ApiKey(api_key) => {
+                pairs.push(("apiKey", api_key.clone()));
+            }
+        }
 
         let format = "json";
-        let crate_name = env!("CARGO_PKG_NAME");
 
         pairs.push(("v", version.to_string()));
-        pairs.push(("c", crate_name.to_string()));
+        pairs.push(("c", client_name.to_string()));
         pairs.push(("f", format.to_string()));
 
         pairs.into_iter()
     }
 }
 
-pub type Result<T> = reqwest::Result<T>;
+pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[derive(Debug)]
-pub enum SubsonicResponseError {
-    ApiError(api::Error),
-    TypeError(api::Response),
+pub enum Error {
+    /// The request couldn't be sent or its response couldn't be read.
+    Transport(reqwest::Error),
+    /// The server answered with an unsuccessful HTTP status.
+    Status(reqwest::StatusCode),
+    /// The body isn't a Subsonic response. `body` holds the start of it.
+    Decode {
+        source: serde_json::Error,
+        body: String,
+    },
+    /// The server reported an error.
+    Api(api::Error),
+    /// The server answered with a different kind of response than was asked for, or none at all.
+    UnexpectedResponse(Option<Box<api::Response>>),
+    /// A parameter was rejected before sending the request.
+    InvalidInput(String),
+    /// Waiting for a library scan timed out. Holds the last status seen.
+    ScanTimeout(api::ScanStatus),
+    /// The endpoint requires a newer API version than the one negotiated with the server.
+    UnsupportedVersion {
+        required: semver::Version,
+        version: semver::Version,
+    },
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        Self::Transport(error)
+    }
 }
 
-impl From<api::Response> for SubsonicResponseError {
+impl From<api::Response> for Error {
     fn from(response: api::Response) -> Self {
         match response {
-            api::Response::Error(error) => Self::ApiError(error),
-            _ => Self::TypeError(response),
+            api::Response::Error(error) => Self::Api(error),
+            _ => Self::UnexpectedResponse(Some(Box::new(response))),
         }
     }
 }
 
-impl std::fmt::Display for SubsonicResponseError {
+impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Error::Transport(error) => write!(f, "transport error: {}", error),
+            Error::Status(status) => write!(f, "unexpected HTTP status: {}", status),
+            Error::Decode { source, body } => {
+                write!(f, "failed to decode response: {}: {}", source, body)
+            }
+            Error::Api(api::Error {
+                code,
+                message: Some(message),
+            }) => write!(f, "API error {}: {}", code, message),
+            Error::Api(api::Error {
+                code,
+                message: None,
+            }) => write!(f, "API error {}", code),
+            Error::UnexpectedResponse(Some(response)) => {
+                write!(f, "unexpected response: {:?}", response)
+            }
+            Error::UnexpectedResponse(None) => write!(f, "unexpected empty response"),
+            Error::InvalidInput(message) => write!(f, "invalid input: {}", message),
+            Error::ScanTimeout(status) => match status.count {
+                Some(count) => write!(f, "timed out waiting for the scan, {} files so far", count),
+                None => write!(f, "timed out waiting for the scan"),
+            },
+            Error::UnsupportedVersion { required, version } => write!(
+                f,
+                "requires API version {} but the server supports {}",
+                required, version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(error) => Some(error),
+            Error::Decode { source, .. } => Some(source),
+            _ => None,
+        }
     }
 }
 
-impl std::error::Error for SubsonicResponseError {}
+impl Error {
+    /// The code of an error reported by the server.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Error::Api(error) => Some(error.error_code()),
+            _ => None,
+        }
+    }
+
+    pub fn is_auth_error(&self) -> bool {
+        self.code().is_some_and(ErrorCode::is_auth_error)
+    }
 
-#[derive(Debug)]
-pub struct SubsonicResponse<T> {
-    pub version: String,
-    pub result: std::result::Result<T, SubsonicResponseError>,
+    pub fn is_not_found(&self) -> bool {
+        self.code().is_some_and(ErrorCode::is_not_found)
+    }
+}
+
+/// The error codes documented by the Subsonic API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    Generic,
+    MissingParameter,
+    /// The client must be upgraded.
+    ClientVersionIncompatible,
+    /// The server must be upgraded.
+    ServerVersionIncompatible,
+    WrongCredentials,
+    /// Token authentication isn't supported, e.g. for LDAP users.
+    TokenAuthNotSupported,
+    /// The user isn't allowed to perform the operation.
+    NotAuthorized,
+    TrialExpired,
+    NotFound,
+    Other(i32),
+}
+
+impl ErrorCode {
+    /// Whether the credentials themselves were rejected, as opposed to the user lacking permission
+    /// for an operation.
+    pub fn is_auth_error(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::WrongCredentials | ErrorCode::TokenAuthNotSupported
+        )
+    }
+
+    pub fn is_not_found(self) -> bool {
+        self == ErrorCode::NotFound
+    }
+}
+
+impl From<i32> for ErrorCode {
+    fn from(code: i32) -> Self {
+        match code {
+            0 => ErrorCode::Generic,
+            10 => ErrorCode::MissingParameter,
+            20 => ErrorCode::ClientVersionIncompatible,
+            30 => ErrorCode::ServerVersionIncompatible,
+            40 => ErrorCode::WrongCredentials,
+            41 => ErrorCode::TokenAuthNotSupported,
+            50 => ErrorCode::NotAuthorized,
+            60 => ErrorCode::TrialExpired,
+            70 => ErrorCode::NotFound,
+            code => ErrorCode::Other(code),
+        }
+    }
+}
+
+impl From<ErrorCode> for i32 {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::Generic => 0,
+            ErrorCode::MissingParameter => 10,
+            ErrorCode::ClientVersionIncompatible => 20,
+            ErrorCode::ServerVersionIncompatible => 30,
+            ErrorCode::WrongCredentials => 40,
+            ErrorCode::TokenAuthNotSupported => 41,
+            ErrorCode::NotAuthorized => 50,
+            ErrorCode::TrialExpired => 60,
+            ErrorCode::NotFound => 70,
+            ErrorCode::Other(code) => code,
+        }
+    }
+}
+
+impl api::Error {
+    pub fn error_code(&self) -> ErrorCode {
+        self.code.into()
+    }
+
+    pub fn is_auth_error(&self) -> bool {
+        self.error_code().is_auth_error()
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.error_code().is_not_found()
+    }
 }
 
 impl api::License {
-    pub async fn get(client: &Client) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getLicense")
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getLicense").send().await
     }
 }
 
 impl api::MusicFolders {
-    pub async fn get(client: &Client) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getMusicFolders")
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getMusicFolders").send().await
     }
 }
 
@@ -211,203 +957,1853 @@ pub struct IndexesConfig<'a> {
 }
 
 impl api::Indexes {
-    pub async fn get(client: &Client) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getIndexes")
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getIndexes").send().await
     }
 
-    pub async fn get_with_config(
-        client: &Client,
-        config: &IndexesConfig<'_>,
-    ) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get_with_params("getIndexes", &config)
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+    pub async fn get_with_config(client: &Client, config: &IndexesConfig<'_>) -> Result<Self> {
+        client.get_with_params("getIndexes", &config).send().await
     }
 }
 
 impl api::Directory {
-    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<SubsonicResponse<Self>> {
-        Ok(client
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<Self> {
+        client
             .get("getMusicDirectory")
             .query(&[("id", id.as_ref())])
             .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+            .await
     }
 }
 
 impl api::Genres {
-    pub async fn get(client: &Client) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getGenres")
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getGenres").send().await
     }
 }
 
 impl api::ArtistsID3 {
-    pub async fn get<ID: AsRef<str>>(
-        client: &Client,
-        music_folder_id: Option<ID>,
-    ) -> Result<SubsonicResponse<Self>> {
+    pub async fn get<ID: AsRef<str>>(client: &Client, music_folder_id: Option<ID>) -> Result<Self> {
         let req = client.get("getArtists");
         let req = if let Some(music_folder_id) = music_folder_id {
             req.query(&[("musicFolderId", music_folder_id.as_ref())])
         } else {
             req
         };
-        Ok(req
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+        req.send().await
     }
 }
 
-impl api::ArtistWithAlbumsID3 {
-    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getArtist")
-            .query(&[("id", id.as_ref())])
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
-    }
+#[derive(Debug, Default, serde::Serialize)]
+pub struct LegacySearchConfig<'a> {
+    pub artist: Option<std::borrow::Cow<'a, str>>,
+    pub album: Option<std::borrow::Cow<'a, str>>,
+    pub title: Option<std::borrow::Cow<'a, str>>,
+    pub any: Option<std::borrow::Cow<'a, str>>,
+    pub count: Option<u32>,
+    pub offset: Option<u32>,
+    /// Only return matches newer than this, in milliseconds since the epoch.
+    #[serde(rename = "newerThan")]
+    pub newer_than: Option<u64>,
 }
 
-impl api::AlbumWithSongsID3 {
-    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getAlbum")
-            .query(&[("id", id.as_ref())])
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+impl api::SearchResult {
+    /// Deprecated since 1.4.0, prefer `SearchResult2` or `SearchResult3`.
+    pub async fn get(client: &Client, config: &LegacySearchConfig<'_>) -> Result<Self> {
+        client.get_with_params("search", config).send().await
     }
 }
 
-impl api::Child {
-    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<SubsonicResponse<Self>> {
-        Ok(client
+#[derive(Debug, Default, serde::Serialize)]
+pub struct SearchConfig<'a> {
+    #[serde(rename = "artistCount")]
+    pub artist_count: Option<u32>,
+    #[serde(rename = "artistOffset")]
+    pub artist_offset: Option<u32>,
+    #[serde(rename = "albumCount")]
+    pub album_count: Option<u32>,
+    #[serde(rename = "albumOffset")]
+    pub album_offset: Option<u32>,
+    #[serde(rename = "songCount")]
+    pub song_count: Option<u32>,
+    #[serde(rename = "songOffset")]
+    pub song_offset: Option<u32>,
+    #[serde(rename = "musicFolderId")]
+    pub music_folder_id: Option<std::borrow::Cow<'a, str>>,
+}
+
+#[derive(serde::Serialize)]
+struct SearchParams<'a> {
+    query: &'a str,
+    #[serde(flatten)]
+    config: &'a SearchConfig<'a>,
+}
+
+impl api::SearchResult2 {
+    pub async fn get<Q: AsRef<str>>(
+        client: &Client,
+        query: Q,
+        config: &SearchConfig<'_>,
+    ) -> Result<Self> {
+        let params = SearchParams {
+            query: query.as_ref(),
+            config,
+        };
+        client.get_with_params("search2", &params).send().await
+    }
+}
+
+impl api::SearchResult3 {
+    pub async fn get<Q: AsRef<str>>(
+        client: &Client,
+        query: Q,
+        config: &SearchConfig<'_>,
+    ) -> Result<Self> {
+        let params = SearchParams {
+            query: query.as_ref(),
+            config,
+        };
+        client.get_with_params("search3", &params).send().await
+    }
+}
+
+impl api::Playlists {
+    pub async fn get<U: AsRef<str>>(client: &Client, username: Option<U>) -> Result<Self> {
+        let req = client.get("getPlaylists");
+        let req = if let Some(username) = username {
+            req.query(&[("username", username.as_ref())])
+        } else {
+            req
+        };
+        req.send().await
+    }
+}
+
+#[derive(serde::Serialize)]
+struct CreatePlaylistParams<'a> {
+    name: &'a str,
+    #[serde(rename = "songId")]
+    song_id: Vec<&'a str>,
+}
+
+impl api::PlaylistWithSongs {
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<Self> {
+        client
+            .get("getPlaylist")
+            .query(&[("id", id.as_ref())])
+            .send()
+            .await
+    }
+
+    /// Servers older than 1.14.0 don't return the created playlist.
+    pub async fn create<N: AsRef<str>, ID: AsRef<str>>(
+        client: &Client,
+        name: N,
+        song_ids: &[ID],
+    ) -> Result<Option<Self>> {
+        let params = CreatePlaylistParams {
+            name: name.as_ref(),
+            song_id: song_ids.iter().map(AsRef::as_ref).collect(),
+        };
+        client
+            .get_with_params("createPlaylist", &params)
+            .send_optional()
+            .await
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct UpdatePlaylistConfig<'a> {
+    pub name: Option<std::borrow::Cow<'a, str>>,
+    pub comment: Option<std::borrow::Cow<'a, str>>,
+    pub public: Option<bool>,
+    #[serde(rename = "songIdToAdd")]
+    pub song_id_to_add: Vec<std::borrow::Cow<'a, str>>,
+    /// Indexes into the playlist as it was before this update.
+    #[serde(rename = "songIndexToRemove")]
+    pub song_index_to_remove: Vec<u32>,
+}
+
+#[derive(serde::Serialize)]
+struct UpdatePlaylistParams<'a> {
+    #[serde(rename = "playlistId")]
+    playlist_id: &'a str,
+    #[serde(flatten)]
+    config: &'a UpdatePlaylistConfig<'a>,
+}
+
+impl api::Playlist {
+    pub async fn update<ID: AsRef<str>>(
+        client: &Client,
+        id: ID,
+        config: &UpdatePlaylistConfig<'_>,
+    ) -> Result<()> {
+        let params = UpdatePlaylistParams {
+            playlist_id: id.as_ref(),
+            config,
+        };
+        client
+            .get_with_params("updatePlaylist", &params)
+            .send_empty()
+            .await
+    }
+
+    pub async fn delete<ID: AsRef<str>>(client: &Client, id: ID) -> Result<()> {
+        client
+            .get("deletePlaylist")
+            .query(&[("id", id.as_ref())])
+            .send_empty()
+            .await
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AlbumListType<'a> {
+    Random,
+    Newest,
+    Highest,
+    Frequent,
+    Recent,
+    AlphabeticalByName,
+    AlphabeticalByArtist,
+    Starred,
+    /// Albums released between the two years, inclusive. Reverse the range for descending order.
+    ByYear {
+        #[serde(rename = "fromYear")]
+        from: u32,
+        #[serde(rename = "toYear")]
+        to: u32,
+    },
+    ByGenre {
+        genre: std::borrow::Cow<'a, str>,
+    },
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct AlbumListConfig<'a> {
+    pub size: Option<u32>,
+    pub offset: Option<u32>,
+    #[serde(rename = "musicFolderId")]
+    pub music_folder_id: Option<std::borrow::Cow<'a, str>>,
+}
+
+#[derive(serde::Serialize)]
+struct AlbumListParams<'a> {
+    #[serde(flatten)]
+    list_type: &'a AlbumListType<'a>,
+    #[serde(flatten)]
+    config: &'a AlbumListConfig<'a>,
+}
+
+impl api::AlbumList {
+    pub async fn get(
+        client: &Client,
+        list_type: &AlbumListType<'_>,
+        config: &AlbumListConfig<'_>,
+    ) -> Result<Self> {
+        let params = AlbumListParams { list_type, config };
+        client.get_with_params("getAlbumList", &params).send().await
+    }
+}
+
+impl api::AlbumList2 {
+    pub async fn get(
+        client: &Client,
+        list_type: &AlbumListType<'_>,
+        config: &AlbumListConfig<'_>,
+    ) -> Result<Self> {
+        let params = AlbumListParams { list_type, config };
+        client
+            .get_with_params("getAlbumList2", &params)
+            .send()
+            .await
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct RandomSongsConfig<'a> {
+    pub size: Option<u32>,
+    pub genre: Option<std::borrow::Cow<'a, str>>,
+    #[serde(rename = "fromYear")]
+    pub from_year: Option<u32>,
+    #[serde(rename = "toYear")]
+    pub to_year: Option<u32>,
+    #[serde(rename = "musicFolderId")]
+    pub music_folder_id: Option<std::borrow::Cow<'a, str>>,
+}
+
+impl api::RandomSongs {
+    pub async fn get(client: &Client, config: &RandomSongsConfig<'_>) -> Result<Self> {
+        client
+            .get_with_params("getRandomSongs", config)
+            .send()
+            .await
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct SongsByGenreConfig<'a> {
+    pub count: Option<u32>,
+    pub offset: Option<u32>,
+    #[serde(rename = "musicFolderId")]
+    pub music_folder_id: Option<std::borrow::Cow<'a, str>>,
+}
+
+#[derive(serde::Serialize)]
+struct SongsByGenreParams<'a> {
+    genre: &'a str,
+    #[serde(flatten)]
+    config: &'a SongsByGenreConfig<'a>,
+}
+
+impl api::SongsByGenre {
+    pub async fn get<G: AsRef<str>>(
+        client: &Client,
+        genre: G,
+        config: &SongsByGenreConfig<'_>,
+    ) -> Result<Self> {
+        let params = SongsByGenreParams {
+            genre: genre.as_ref(),
+            config,
+        };
+        client
+            .get_with_params("getSongsByGenre", &params)
+            .send()
+            .await
+    }
+}
+
+impl api::ArtistWithAlbumsID3 {
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<Self> {
+        client
+            .get("getArtist")
+            .query(&[("id", id.as_ref())])
+            .send()
+            .await
+    }
+}
+
+impl api::AlbumWithSongsID3 {
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<Self> {
+        client
+            .get("getAlbum")
+            .query(&[("id", id.as_ref())])
+            .send()
+            .await
+    }
+}
+
+impl api::Child {
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<Self> {
+        client
             .get("getSong")
             .query(&[("id", id.as_ref())])
             .send()
+            .await
+    }
+}
+
+impl api::Videos {
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getVideos").send().await
+    }
+}
+
+impl api::VideoInfo {
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<Self> {
+        client
+            .get("getVideoInfo")
+            .query(&[("id", id.as_ref())])
+            .send()
+            .await
+    }
+}
+
+impl api::ArtistInfo {
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<Self> {
+        client
+            .get("getArtistInfo")
+            .query(&[("id", id.as_ref())])
+            .send()
+            .await
+    }
+}
+
+impl api::ArtistInfo2 {
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<Self> {
+        client
+            .get("getArtistInfo2")
+            .query(&[("id", id.as_ref())])
+            .send()
+            .await
+    }
+}
+
+impl api::AlbumInfo {
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<Self> {
+        client
+            .get("getAlbumInfo")
+            .query(&[("id", id.as_ref())])
+            .send()
+            .await
+    }
+
+    pub async fn get_id3<ID: AsRef<str>>(client: &Client, id: ID) -> Result<Self> {
+        client
+            .get("getAlbumInfo2")
+            .query(&[("id", id.as_ref())])
+            .send()
+            .await
+    }
+}
+
+impl api::Child {
+    pub async fn download(&self, client: &Client) -> Result<reqwest::Response> {
+        client
+            .get("download")
+            .query(&[("id", self.id.as_str())])
+            .execute()
+            .await
+    }
+
+    pub fn download_request(&self, client: &Client) -> Result<reqwest::Request> {
+        client
+            .get("download")
+            .query(&[("id", self.id.as_str())])
+            .build()
+    }
+
+    /// Failures, which the server reports as a JSON response instead of audio, are returned as
+    /// errors before any data is streamed.
+    pub async fn stream(
+        &self,
+        client: &Client,
+        config: &StreamConfig<'_>,
+    ) -> Result<impl futures_util::Stream<Item = Result<bytes::Bytes>>> {
+        use futures_util::TryStreamExt;
+
+        let params = MediaParams {
+            id: self.id.as_str(),
+            config,
+        };
+        Ok(client
+            .get_with_params("stream", &params)
+            .execute()
             .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+            .bytes_stream()
+            .map_err(Error::from))
+    }
+
+    pub fn stream_request(
+        &self,
+        client: &Client,
+        config: &StreamConfig<'_>,
+    ) -> Result<reqwest::Request> {
+        let params = MediaParams {
+            id: self.id.as_str(),
+            config,
+        };
+        client.get_with_params("stream", &params).build()
+    }
+
+    pub fn hls_request(&self, client: &Client, config: &HlsConfig<'_>) -> Result<reqwest::Request> {
+        hls_request(client, &self.id, config)
+    }
+}
+
+fn hls_request(client: &Client, id: &str, config: &HlsConfig<'_>) -> Result<reqwest::Request> {
+    let params = MediaParams { id, config };
+    client.get_with_params("hls.m3u8", &params).build()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl serde::Serialize for VideoSize {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{}x{}", self.width, self.height))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StreamFormat<'a> {
+    /// Disables transcoding.
+    Raw,
+    /// A transcoding target, e.g. `mp3` or `flv`.
+    Transcode(std::borrow::Cow<'a, str>),
+}
+
+impl serde::Serialize for StreamFormat<'_> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            StreamFormat::Raw => serializer.serialize_str("raw"),
+            StreamFormat::Transcode(format) => serializer.serialize_str(format),
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct StreamConfig<'a> {
+    /// In Kbps, 0 for no limit.
+    #[serde(rename = "maxBitRate")]
+    pub max_bit_rate: Option<u32>,
+    pub format: Option<StreamFormat<'a>>,
+    /// Seconds to skip into a video.
+    #[serde(rename = "timeOffset")]
+    pub time_offset: Option<u32>,
+    /// Video only.
+    pub size: Option<VideoSize>,
+    #[serde(rename = "estimateContentLength")]
+    pub estimate_content_length: Option<bool>,
+    /// Stream the converted version of a video, if one is available.
+    pub converted: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HlsBitRate {
+    /// In Kbps.
+    pub bit_rate: u32,
+    pub size: Option<VideoSize>,
+}
+
+impl serde::Serialize for HlsBitRate {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match self.size {
+            Some(VideoSize { width, height }) => {
+                serializer.collect_str(&format_args!("{}@{}x{}", self.bit_rate, width, height))
+            }
+            None => serializer.collect_str(&self.bit_rate),
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct HlsConfig<'a> {
+    /// Each bit rate becomes a variant of the playlist.
+    #[serde(rename = "bitRate")]
+    pub bit_rate: Vec<HlsBitRate>,
+    #[serde(rename = "audioTrack")]
+    pub audio_track: Option<std::borrow::Cow<'a, str>>,
+}
+
+#[derive(serde::Serialize)]
+struct MediaParams<'a, C> {
+    id: &'a str,
+    #[serde(flatten)]
+    config: &'a C,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct StarConfig<'a> {
+    /// Songs, or albums and artists when browsing by folder.
+    pub id: Vec<std::borrow::Cow<'a, str>>,
+    #[serde(rename = "albumId")]
+    pub album_id: Vec<std::borrow::Cow<'a, str>>,
+    #[serde(rename = "artistId")]
+    pub artist_id: Vec<std::borrow::Cow<'a, str>>,
+}
+
+/// A user rating between 1 and 5.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rating(i32);
+
+impl Rating {
+    pub fn get(self) -> i32 {
+        self.0
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidRating(pub i32);
+
+impl std::fmt::Display for InvalidRating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rating must be between 1 and 5, got {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidRating {}
+
+impl From<InvalidRating> for Error {
+    fn from(error: InvalidRating) -> Self {
+        Self::InvalidInput(error.to_string())
+    }
+}
+
+impl TryFrom<i32> for Rating {
+    type Error = InvalidRating;
+
+    fn try_from(rating: i32) -> std::result::Result<Self, Self::Error> {
+        if (1..=5).contains(&rating) {
+            Ok(Self(rating))
+        } else {
+            Err(InvalidRating(rating))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScrobbleEntry<'a> {
+    pub id: std::borrow::Cow<'a, str>,
+    /// When the song was listened to. Defaults to the time of the request.
+    pub time: Option<std::time::SystemTime>,
+}
+
+#[derive(serde::Serialize)]
+struct ScrobbleParams<'a> {
+    id: Vec<&'a str>,
+    time: Vec<u64>,
+    submission: bool,
+}
+
+impl<'a> ScrobbleParams<'a> {
+    fn new(entries: &'a [ScrobbleEntry<'a>], submission: bool) -> Self {
+        // Times are matched to ids by position so either every entry gets one or none do.
+        let time = if entries.iter().any(|entry| entry.time.is_some()) {
+            let now = std::time::SystemTime::now();
+            entries
+                .iter()
+                .map(|entry| unix_millis(entry.time.unwrap_or(now)))
+                .collect()
+        } else {
+            vec![]
+        };
+        Self {
+            id: entries.iter().map(|entry| entry.id.as_ref()).collect(),
+            time,
+            submission,
+        }
+    }
+}
+
+impl Client {
+    pub async fn star(&self, config: &StarConfig<'_>) -> Result<()> {
+        self.get_with_params("star", config).send_empty().await
+    }
+
+    pub async fn unstar(&self, config: &StarConfig<'_>) -> Result<()> {
+        self.get_with_params("unstar", config).send_empty().await
+    }
+
+    /// Rates a song, album or artist. Build the rating with `Rating::try_from` so it is validated
+    /// before sending. A rating of `None` removes the current rating.
+    pub async fn set_rating<ID: AsRef<str>>(&self, id: ID, rating: Option<Rating>) -> Result<()> {
+        let rating = rating.map_or(0, Rating::get);
+        self.get("setRating")
+            .query(&[("id", id.as_ref())])
+            .query(&[("rating", rating)])
+            .send_empty()
+            .await
+    }
+
+    /// Registers the local playback of one or more songs. When `submission` is false the songs
+    /// are only reported as now playing.
+    pub async fn scrobble(&self, entries: &[ScrobbleEntry<'_>], submission: bool) -> Result<()> {
+        let params = ScrobbleParams::new(entries, submission);
+        self.get_with_params("scrobble", &params).send_empty().await
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct PodcastsConfig<'a> {
+    #[serde(rename = "includeEpisodes")]
+    pub include_episodes: Option<bool>,
+    /// Only return this channel.
+    pub id: Option<std::borrow::Cow<'a, str>>,
+}
+
+impl api::Podcasts {
+    pub async fn get(client: &Client, config: &PodcastsConfig<'_>) -> Result<Self> {
+        client.get_with_params("getPodcasts", config).send().await
+    }
+
+    /// Asks the server to check all channels for new episodes.
+    pub async fn refresh(client: &Client) -> Result<()> {
+        client.get("refreshPodcasts").send_empty().await
+    }
+}
+
+impl api::NewestPodcasts {
+    pub async fn get(client: &Client, count: Option<u32>) -> Result<Self> {
+        let req = client.get("getNewestPodcasts");
+        let req = if let Some(count) = count {
+            req.query(&[("count", count)])
+        } else {
+            req
+        };
+        req.send().await
+    }
+}
+
+impl api::PodcastChannel {
+    pub async fn create<U: AsRef<str>>(client: &Client, url: U) -> Result<()> {
+        client
+            .get("createPodcastChannel")
+            .query(&[("url", url.as_ref())])
+            .send_empty()
+            .await
+    }
+
+    pub async fn delete<ID: AsRef<str>>(client: &Client, id: ID) -> Result<()> {
+        client
+            .get("deletePodcastChannel")
+            .query(&[("id", id.as_ref())])
+            .send_empty()
+            .await
+    }
+}
+
+impl api::PodcastEpisode {
+    pub async fn delete<ID: AsRef<str>>(client: &Client, id: ID) -> Result<()> {
+        client
+            .get("deletePodcastEpisode")
+            .query(&[("id", id.as_ref())])
+            .send_empty()
+            .await
+    }
+
+    /// Asks the server to start downloading the episode. Use `Child::download` to fetch an
+    /// episode that has already been downloaded.
+    pub async fn request_download<ID: AsRef<str>>(client: &Client, id: ID) -> Result<()> {
+        client
+            .get("downloadPodcastEpisode")
+            .query(&[("id", id.as_ref())])
+            .send_empty()
+            .await
+    }
+}
+
+impl Client {
+    /// Controls playback on the server's own audio hardware.
+    pub fn jukebox(&self) -> Jukebox<'_> {
+        Jukebox { client: self }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Jukebox<'a> {
+    client: &'a Client,
+}
+
+impl Jukebox<'_> {
+    /// The current playlist along with the playback status.
+    pub async fn get(&self) -> Result<api::JukeboxPlaylist> {
+        self.action("get").send().await
+    }
+
+    pub async fn status(&self) -> Result<api::JukeboxStatus> {
+        self.action("status").send().await
+    }
+
+    /// Replaces the playlist with the given songs.
+    pub async fn set<ID: AsRef<str>>(&self, ids: &[ID]) -> Result<api::JukeboxStatus> {
+        self.action("set").query(&Self::ids(ids)).send().await
+    }
+
+    pub async fn start(&self) -> Result<api::JukeboxStatus> {
+        self.action("start").send().await
+    }
+
+    pub async fn stop(&self) -> Result<api::JukeboxStatus> {
+        self.action("stop").send().await
+    }
+
+    /// Jumps to the song at `index` in the playlist, `offset` seconds in.
+    pub async fn skip(&self, index: u32, offset: Option<u32>) -> Result<api::JukeboxStatus> {
+        let req = self.action("skip").query(&[("index", index)]);
+        let req = if let Some(offset) = offset {
+            req.query(&[("offset", offset)])
+        } else {
+            req
+        };
+        req.send().await
+    }
+
+    /// Appends the given songs to the playlist.
+    pub async fn add<ID: AsRef<str>>(&self, ids: &[ID]) -> Result<api::JukeboxStatus> {
+        self.action("add").query(&Self::ids(ids)).send().await
+    }
+
+    pub async fn clear(&self) -> Result<api::JukeboxStatus> {
+        self.action("clear").send().await
+    }
+
+    /// Removes the song at `index` from the playlist.
+    pub async fn remove(&self, index: u32) -> Result<api::JukeboxStatus> {
+        self.action("remove")
+            .query(&[("index", index)])
+            .send()
+            .await
+    }
+
+    pub async fn shuffle(&self) -> Result<api::JukeboxStatus> {
+        self.action("shuffle").send().await
+    }
+
+    pub async fn set_gain(&self, gain: JukeboxGain) -> Result<api::JukeboxStatus> {
+        self.action("setGain")
+            .query(&[("gain", gain.0.to_string())])
+            .send()
+            .await
+    }
+
+    fn action(&self, action: &str) -> RequestBuilder<'_> {
+        self.client
+            .get("jukeboxControl")
+            .query(&[("action", action)])
+    }
+
+    fn ids<ID: AsRef<str>>(ids: &[ID]) -> Vec<(&'static str, &str)> {
+        ids.iter().map(|id| ("id", id.as_ref())).collect()
+    }
+}
+
+/// A jukebox volume between 0.0 and 1.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JukeboxGain(f32);
+
+impl JukeboxGain {
+    pub fn get(self) -> f32 {
+        self.0
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidGain(pub f32);
+
+impl std::fmt::Display for InvalidGain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "gain must be between 0.0 and 1.0, got {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidGain {}
+
+impl From<InvalidGain> for Error {
+    fn from(error: InvalidGain) -> Self {
+        Self::InvalidInput(error.to_string())
+    }
+}
+
+impl TryFrom<f32> for JukeboxGain {
+    type Error = InvalidGain;
+
+    fn try_from(gain: f32) -> std::result::Result<Self, Self::Error> {
+        if (0.0..=1.0).contains(&gain) {
+            Ok(Self(gain))
+        } else {
+            Err(InvalidGain(gain))
+        }
+    }
+}
+
+/// Sends a password as `enc:` followed by its hex encoding, so it isn't in plain text in server
+/// logs.
+struct EncodedPassword<'a>(&'a str);
+
+impl std::fmt::Display for EncodedPassword<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("enc:")?;
+        for byte in self.0.as_bytes() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl serde::Serialize for EncodedPassword<'_> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+fn serialize_optional_password<S: serde::Serializer>(
+    password: &Option<std::borrow::Cow<'_, str>>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match password {
+        Some(password) => serializer.serialize_some(&EncodedPassword(password)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Mirrors the role flags of `api::User`. Roles left as `None` are left to the server default
+/// when creating a user and unchanged when updating one.
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct UserRoles {
+    #[serde(rename = "adminRole")]
+    pub admin_role: Option<bool>,
+    #[serde(rename = "settingsRole")]
+    pub settings_role: Option<bool>,
+    #[serde(rename = "streamRole")]
+    pub stream_role: Option<bool>,
+    #[serde(rename = "jukeboxRole")]
+    pub jukebox_role: Option<bool>,
+    #[serde(rename = "downloadRole")]
+    pub download_role: Option<bool>,
+    #[serde(rename = "uploadRole")]
+    pub upload_role: Option<bool>,
+    #[serde(rename = "playlistRole")]
+    pub playlist_role: Option<bool>,
+    #[serde(rename = "coverArtRole")]
+    pub cover_art_role: Option<bool>,
+    #[serde(rename = "commentRole")]
+    pub comment_role: Option<bool>,
+    #[serde(rename = "podcastRole")]
+    pub podcast_role: Option<bool>,
+    #[serde(rename = "shareRole")]
+    pub share_role: Option<bool>,
+    #[serde(rename = "videoConversionRole")]
+    pub video_conversion_role: Option<bool>,
+}
+
+impl From<&api::User> for UserRoles {
+    fn from(user: &api::User) -> Self {
+        Self {
+            admin_role: Some(user.admin_role),
+            settings_role: Some(user.settings_role),
+            stream_role: Some(user.stream_role),
+            jukebox_role: Some(user.jukebox_role),
+            download_role: Some(user.download_role),
+            upload_role: Some(user.upload_role),
+            playlist_role: Some(user.playlist_role),
+            cover_art_role: Some(user.cover_art_role),
+            comment_role: Some(user.comment_role),
+            podcast_role: Some(user.podcast_role),
+            share_role: Some(user.share_role),
+            video_conversion_role: Some(user.video_conversion_role),
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct CreateUserConfig<'a> {
+    #[serde(rename = "ldapAuthenticated")]
+    pub ldap_authenticated: Option<bool>,
+    #[serde(flatten)]
+    pub roles: UserRoles,
+    /// The folders the user may access, defaults to all of them.
+    #[serde(rename = "musicFolderId")]
+    pub music_folder_id: Vec<std::borrow::Cow<'a, str>>,
+}
+
+#[derive(serde::Serialize)]
+struct CreateUserParams<'a> {
+    username: &'a str,
+    password: EncodedPassword<'a>,
+    email: &'a str,
+    #[serde(flatten)]
+    config: &'a CreateUserConfig<'a>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct UpdateUserConfig<'a> {
+    #[serde(serialize_with = "serialize_optional_password")]
+    pub password: Option<std::borrow::Cow<'a, str>>,
+    pub email: Option<std::borrow::Cow<'a, str>>,
+    #[serde(rename = "ldapAuthenticated")]
+    pub ldap_authenticated: Option<bool>,
+    #[serde(flatten)]
+    pub roles: UserRoles,
+    /// Replaces the folders the user may access. Left unchanged when empty.
+    #[serde(rename = "musicFolderId")]
+    pub music_folder_id: Vec<std::borrow::Cow<'a, str>>,
+    /// In Kbps, 0 for no limit.
+    #[serde(rename = "maxBitRate")]
+    pub max_bit_rate: Option<u32>,
+}
+
+#[derive(serde::Serialize)]
+struct UpdateUserParams<'a> {
+    username: &'a str,
+    #[serde(flatten)]
+    config: &'a UpdateUserConfig<'a>,
+}
+
+impl api::User {
+    pub async fn get<U: AsRef<str>>(client: &Client, username: U) -> Result<Self> {
+        client
+            .get("getUser")
+            .query(&[("username", username.as_ref())])
+            .send()
+            .await
+    }
+
+    pub async fn create<U: AsRef<str>, P: AsRef<str>, E: AsRef<str>>(
+        client: &Client,
+        username: U,
+        password: P,
+        email: E,
+        config: &CreateUserConfig<'_>,
+    ) -> Result<()> {
+        let params = CreateUserParams {
+            username: username.as_ref(),
+            password: EncodedPassword(password.as_ref()),
+            email: email.as_ref(),
+            config,
+        };
+        client
+            .get_with_params("createUser", &params)
+            .send_empty()
+            .await
+    }
+
+    pub async fn update<U: AsRef<str>>(
+        client: &Client,
+        username: U,
+        config: &UpdateUserConfig<'_>,
+    ) -> Result<()> {
+        let params = UpdateUserParams {
+            username: username.as_ref(),
+            config,
+        };
+        client
+            .get_with_params("updateUser", &params)
+            .send_empty()
+            .await
+    }
+
+    pub async fn delete<U: AsRef<str>>(client: &Client, username: U) -> Result<()> {
+        client
+            .get("deleteUser")
+            .query(&[("username", username.as_ref())])
+            .send_empty()
+            .await
+    }
+
+    pub async fn change_password<U: AsRef<str>, P: AsRef<str>>(
+        client: &Client,
+        username: U,
+        password: P,
+    ) -> Result<()> {
+        let password = EncodedPassword(password.as_ref()).to_string();
+        client
+            .get("changePassword")
+            .query(&[("username", username.as_ref()), ("password", &password)])
+            .send_empty()
+            .await
+    }
+}
+
+impl api::Users {
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getUsers").send().await
+    }
+}
+
+impl api::Bookmarks {
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getBookmarks").send().await
+    }
+}
+
+impl api::Bookmark {
+    /// Creates or updates the bookmark for a media file. `position` is in milliseconds.
+    pub async fn create<ID: AsRef<str>>(
+        client: &Client,
+        id: ID,
+        position: u64,
+        comment: Option<&str>,
+    ) -> Result<()> {
+        let req = client
+            .get("createBookmark")
+            .query(&[("id", id.as_ref())])
+            .query(&[("position", position)]);
+        let req = if let Some(comment) = comment {
+            req.query(&[("comment", comment)])
+        } else {
+            req
+        };
+        req.send_empty().await
+    }
+
+    pub async fn delete<ID: AsRef<str>>(client: &Client, id: ID) -> Result<()> {
+        client
+            .get("deleteBookmark")
+            .query(&[("id", id.as_ref())])
+            .send_empty()
+            .await
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SavePlayQueueParams<'a> {
+    id: Vec<&'a str>,
+    current: Option<&'a str>,
+    position: Option<u64>,
+}
+
+impl api::PlayQueue {
+    /// Returns `None` if the user has never saved a play queue.
+    pub async fn get(client: &Client) -> Result<Option<Self>> {
+        client.get("getPlayQueue").send_optional().await
+    }
+
+    /// `current` is the id of the playing song and `position` how far into it playback is, in
+    /// milliseconds.
+    pub async fn save<ID: AsRef<str>>(
+        client: &Client,
+        ids: &[ID],
+        current: Option<&str>,
+        position: Option<u64>,
+    ) -> Result<()> {
+        let params = SavePlayQueueParams {
+            id: ids.iter().map(AsRef::as_ref).collect(),
+            current,
+            position,
+        };
+        client
+            .get_with_params("savePlayQueue", &params)
+            .send_empty()
+            .await
+    }
+}
+
+#[derive(Debug)]
+pub enum PlayQueueSyncOutcome {
+    /// The local queue was saved to the server.
+    Saved,
+    /// Another device changed the server's queue since the last sync so nothing was saved. The
+    /// local queue should be replaced with this one.
+    Conflict(api::PlayQueue),
+}
+
+/// Keeps a local play queue in step with the server's without clobbering changes made by other
+/// devices.
+///
+/// The server stamps its queue with `changed` and `changedBy` whenever it is saved. This
+/// remembers the `changed` stamp of the last queue it saw, so a different stamp means someone else
+/// saved in the meantime. After saving, the queue is fetched again and only taken as our own save
+/// if `changedBy` is this client's name and it holds the songs that were sent.
+#[derive(Debug, Default, Clone)]
+pub struct PlayQueueSync {
+    changed: Option<String>,
+}
+
+impl PlayQueueSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fetches the server's queue and marks it as seen.
+    pub async fn pull(&mut self, client: &Client) -> Result<Option<api::PlayQueue>> {
+        let play_queue = api::PlayQueue::get(client).await?;
+        self.changed = play_queue
+            .as_ref()
+            .map(|play_queue| play_queue.changed.clone());
+        Ok(play_queue)
+    }
+
+    /// Saves the local queue unless the server's queue has changed since it was last seen.
+    pub async fn push<ID: AsRef<str>>(
+        &mut self,
+        client: &Client,
+        ids: &[ID],
+        current: Option<&str>,
+        position: Option<u64>,
+    ) -> Result<PlayQueueSyncOutcome> {
+        let remote = api::PlayQueue::get(client).await?;
+        if let Some(remote) = remote.filter(|remote| self.is_stale(remote)) {
+            self.changed = Some(remote.changed.clone());
+            return Ok(PlayQueueSyncOutcome::Conflict(remote));
+        }
+
+        api::PlayQueue::save(client, ids, current, position).await?;
+
+        // Pick up the stamp of our own save so it isn't mistaken for another device's. Another
+        // device may have saved right after us though, in which case its queue wins.
+        match self.pull(client).await? {
+            Some(remote) if !Self::is_own_save(client, ids, &remote) => {
+                Ok(PlayQueueSyncOutcome::Conflict(remote))
+            }
+            _ => Ok(PlayQueueSyncOutcome::Saved),
+        }
+    }
+
+    fn is_stale(&self, remote: &api::PlayQueue) -> bool {
+        self.changed.as_deref() != Some(remote.changed.as_str())
+    }
+
+    fn is_own_save<ID: AsRef<str>>(client: &Client, ids: &[ID], remote: &api::PlayQueue) -> bool {
+        remote.changed_by == client.client_name()
+            && remote
+                .entry
+                .iter()
+                .map(|entry| entry.id.as_str())
+                .eq(ids.iter().map(AsRef::as_ref))
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ShareConfig<'a> {
+    pub description: Option<std::borrow::Cow<'a, str>>,
+    #[serde(serialize_with = "serialize_optional_time")]
+    pub expires: Option<std::time::SystemTime>,
+}
+
+#[derive(serde::Serialize)]
+struct ShareParams<'a> {
+    id: Vec<&'a str>,
+    #[serde(flatten)]
+    config: &'a ShareConfig<'a>,
+}
+
+impl api::Shares {
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getShares").send().await
+    }
+
+    /// Shares songs, albums or directories. The response contains the new share.
+    pub async fn create<ID: AsRef<str>>(
+        client: &Client,
+        ids: &[ID],
+        config: &ShareConfig<'_>,
+    ) -> Result<Self> {
+        let params = ShareParams {
+            id: ids.iter().map(AsRef::as_ref).collect(),
+            config,
+        };
+        client.get_with_params("createShare", &params).send().await
+    }
+}
+
+impl api::Share {
+    /// The public link to the share.
+    pub fn parsed_url(&self) -> std::result::Result<reqwest::Url, url::ParseError> {
+        reqwest::Url::parse(&self.url)
+    }
+
+    pub async fn update<ID: AsRef<str>>(
+        client: &Client,
+        id: ID,
+        config: &ShareConfig<'_>,
+    ) -> Result<()> {
+        let params = ShareParams {
+            id: vec![id.as_ref()],
+            config,
+        };
+        client
+            .get_with_params("updateShare", &params)
+            .send_empty()
+            .await
+    }
+
+    pub async fn delete<ID: AsRef<str>>(client: &Client, id: ID) -> Result<()> {
+        client
+            .get("deleteShare")
+            .query(&[("id", id.as_ref())])
+            .send_empty()
+            .await
+    }
+}
+
+impl api::InternetRadioStations {
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getInternetRadioStations").send().await
+    }
+}
+
+#[derive(serde::Serialize)]
+struct InternetRadioStationParams<'a> {
+    id: Option<&'a str>,
+    name: &'a str,
+    #[serde(rename = "streamUrl")]
+    stream_url: reqwest::Url,
+    #[serde(rename = "homepageUrl")]
+    home_page_url: Option<reqwest::Url>,
+}
+
+impl<'a> InternetRadioStationParams<'a> {
+    /// Fails without sending anything if either URL is invalid.
+    fn new<S: reqwest::IntoUrl, H: reqwest::IntoUrl>(
+        id: Option<&'a str>,
+        name: &'a str,
+        stream_url: S,
+        home_page_url: Option<H>,
+    ) -> Result<Self> {
+        let invalid_url = |error| Error::InvalidInput(format!("invalid URL: {}", error));
+        Ok(Self {
+            id,
+            name,
+            stream_url: stream_url.into_url().map_err(invalid_url)?,
+            home_page_url: home_page_url
+                .map(|url| url.into_url())
+                .transpose()
+                .map_err(invalid_url)?,
+        })
+    }
+}
+
+impl api::InternetRadioStation {
+    pub async fn create<N: AsRef<str>, S: reqwest::IntoUrl, H: reqwest::IntoUrl>(
+        client: &Client,
+        name: N,
+        stream_url: S,
+        home_page_url: Option<H>,
+    ) -> Result<()> {
+        let params =
+            InternetRadioStationParams::new(None, name.as_ref(), stream_url, home_page_url)?;
+        client
+            .get_with_params("createInternetRadioStation", &params)
+            .send_empty()
+            .await
+    }
+
+    pub async fn update<ID: AsRef<str>, N: AsRef<str>, S: reqwest::IntoUrl, H: reqwest::IntoUrl>(
+        client: &Client,
+        id: ID,
+        name: N,
+        stream_url: S,
+        home_page_url: Option<H>,
+    ) -> Result<()> {
+        let params = InternetRadioStationParams::new(
+            Some(id.as_ref()),
+            name.as_ref(),
+            stream_url,
+            home_page_url,
+        )?;
+        client
+            .get_with_params("updateInternetRadioStation", &params)
+            .send_empty()
+            .await
+    }
+
+    pub async fn delete<ID: AsRef<str>>(client: &Client, id: ID) -> Result<()> {
+        client
+            .get("deleteInternetRadioStation")
+            .query(&[("id", id.as_ref())])
+            .send_empty()
+            .await
+    }
+}
+
+impl api::ChatMessages {
+    /// `since` is in milliseconds since the epoch.
+    pub async fn get(client: &Client, since: Option<u64>) -> Result<Self> {
+        let req = client.get("getChatMessages");
+        let req = if let Some(since) = since {
+            req.query(&[("since", since)])
+        } else {
+            req
+        };
+        req.send().await
+    }
+
+    /// Polls for messages every `interval`, yielding each batch of messages newer than any seen
+    /// before, oldest first. Starts with the messages after `since`, or all of them if `None`.
+    pub fn poll(
+        client: &Client,
+        since: Option<u64>,
+        interval: std::time::Duration,
+    ) -> impl futures_util::Stream<Item = Result<Vec<api::ChatMessage>>> + '_ {
+        futures_util::stream::unfold((since, true), move |(mut since, mut first)| async move {
+            loop {
+                if !first {
+                    tokio::time::sleep(interval).await;
+                }
+                first = false;
+
+                let messages = match Self::get(client, since).await {
+                    Ok(messages) => Self::newer_than(messages, &mut since),
+                    Err(error) => return Some((Err(error), (since, first))),
+                };
+                if !messages.is_empty() {
+                    return Some((Ok(messages), (since, first)));
+                }
+            }
+        })
+    }
+
+    /// Sorts out the messages after `since` and moves `since` up to the newest of them.
+    fn newer_than(messages: Self, since: &mut Option<u64>) -> Vec<api::ChatMessage> {
+        let mut messages = messages
+            .chat_message
+            .into_iter()
+            .filter(|message| since.is_none_or(|since| message.time > since as i64))
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|message| message.time);
+        if let Some(newest) = messages.last() {
+            *since = Some(newest.time as u64);
+        }
+        messages
+    }
+}
+
+impl api::ChatMessage {
+    pub async fn add<M: AsRef<str>>(client: &Client, message: M) -> Result<()> {
+        client
+            .get("addChatMessage")
+            .query(&[("message", message.as_ref())])
+            .send_empty()
+            .await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanWaitConfig {
+    pub poll_interval: std::time::Duration,
+    /// Give up waiting after this long with `Error::ScanTimeout`. The scan itself carries on.
+    pub timeout: Option<std::time::Duration>,
+}
+
+impl Default for ScanWaitConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: std::time::Duration::from_secs(1),
+            timeout: None,
+        }
+    }
+}
+
+impl api::ScanStatus {
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getScanStatus").send().await
+    }
+
+    /// Starts rescanning the media library.
+    pub async fn start(client: &Client) -> Result<Self> {
+        client.get("startScan").send().await
+    }
+
+    /// Starts a scan and resolves once it has finished. If the timeout elapses first this fails
+    /// with `Error::ScanTimeout`, so success always means the scan is done.
+    pub async fn start_and_wait(client: &Client, config: &ScanWaitConfig) -> Result<Self> {
+        let deadline = config
+            .timeout
+            .map(|timeout| tokio::time::Instant::now() + timeout);
+        let mut status = Self::start(client).await?;
+        loop {
+            if !status.scanning {
+                return Ok(status);
+            }
+            let wake = match deadline {
+                Some(deadline) if tokio::time::Instant::now() >= deadline => {
+                    return Err(Error::ScanTimeout(status))
+                }
+                Some(deadline) => deadline.min(tokio::time::Instant::now() + config.poll_interval),
+                None => tokio::time::Instant::now() + config.poll_interval,
+            };
+            tokio::time::sleep_until(wake).await;
+            status = Self::get(client).await?;
+        }
+    }
+
+    /// Polls the scan status every `poll_interval`, ending after the first status that isn't
+    /// `scanning` or the first error. Use `count` to report progress.
+    pub fn progress(
+        client: &Client,
+        poll_interval: std::time::Duration,
+    ) -> impl futures_util::Stream<Item = Result<Self>> + '_ {
+        futures_util::stream::unfold(Some(true), move |state| async move {
+            let first = state?;
+            if !first {
+                tokio::time::sleep(poll_interval).await;
+            }
+            let status = Self::get(client).await;
+            let next = match &status {
+                Ok(Self { scanning: true, .. }) => Some(false),
+                _ => None,
+            };
+            Some((status, next))
+        })
+    }
+}
+
+impl api::NowPlaying {
+    pub async fn get(client: &Client) -> Result<Self> {
+        client.get("getNowPlaying").send().await
+    }
+}
+
+impl api::SimilarSongs {
+    /// Songs similar to an artist, album or song, mixed with songs from similar artists.
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID, count: Option<u32>) -> Result<Self> {
+        let req = client.get("getSimilarSongs").query(&[("id", id.as_ref())]);
+        let req = if let Some(count) = count {
+            req.query(&[("count", count)])
+        } else {
+            req
+        };
+        req.send().await
+    }
+}
+
+impl api::SimilarSongs2 {
+    /// Like `SimilarSongs` but for an artist, album or song organized by ID3 tags.
+    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID, count: Option<u32>) -> Result<Self> {
+        let req = client.get("getSimilarSongs2").query(&[("id", id.as_ref())]);
+        let req = if let Some(count) = count {
+            req.query(&[("count", count)])
+        } else {
+            req
+        };
+        req.send().await
+    }
+}
+
+impl api::TopSongs {
+    /// `artist` is the artist's name, not an id.
+    pub async fn get<A: AsRef<str>>(
+        client: &Client,
+        artist: A,
+        count: Option<u32>,
+    ) -> Result<Self> {
+        let req = client
+            .get("getTopSongs")
+            .query(&[("artist", artist.as_ref())]);
+        let req = if let Some(count) = count {
+            req.query(&[("count", count)])
+        } else {
+            req
+        };
+        req.send().await
+    }
+}
+
+impl api::Starred {
+    pub async fn get<ID: AsRef<str>>(client: &Client, music_folder_id: Option<ID>) -> Result<Self> {
+        let req = client.get("getStarred");
+        let req = if let Some(music_folder_id) = music_folder_id {
+            req.query(&[("musicFolderId", music_folder_id.as_ref())])
+        } else {
+            req
+        };
+        req.send().await
+    }
+}
+
+impl api::Starred2 {
+    pub async fn get<ID: AsRef<str>>(client: &Client, music_folder_id: Option<ID>) -> Result<Self> {
+        let req = client.get("getStarred2");
+        let req = if let Some(music_folder_id) = music_folder_id {
+            req.query(&[("musicFolderId", music_folder_id.as_ref())])
+        } else {
+            req
+        };
+        req.send().await
+    }
+}
+
+/// An artist from either `Starred` (folder based) or `Starred2` (ID3 based).
+#[derive(Debug)]
+pub enum StarredArtist {
+    Folder(api::Artist),
+    Id3(api::ArtistID3),
+}
+
+impl StarredArtist {
+    pub fn id(&self) -> &str {
+        match self {
+            StarredArtist::Folder(artist) => &artist.id,
+            StarredArtist::Id3(artist) => &artist.id,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            StarredArtist::Folder(artist) => &artist.name,
+            StarredArtist::Id3(artist) => &artist.name,
+        }
+    }
+
+    pub fn starred(&self) -> Option<&str> {
+        match self {
+            StarredArtist::Folder(artist) => artist.starred.as_deref(),
+            StarredArtist::Id3(artist) => artist.starred.as_deref(),
+        }
+    }
+}
+
+/// An album from either `Starred` (folder based) or `Starred2` (ID3 based).
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum StarredAlbum {
+    Folder(api::Child),
+    Id3(api::AlbumID3),
+}
+
+impl StarredAlbum {
+    pub fn id(&self) -> &str {
+        match self {
+            StarredAlbum::Folder(album) => &album.id,
+            StarredAlbum::Id3(album) => &album.id,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            StarredAlbum::Folder(album) => album.album.as_deref().unwrap_or(&album.title),
+            StarredAlbum::Id3(album) => &album.name,
+        }
+    }
+
+    pub fn artist(&self) -> Option<&str> {
+        match self {
+            StarredAlbum::Folder(album) => album.artist.as_deref(),
+            StarredAlbum::Id3(album) => album.artist.as_deref(),
+        }
+    }
+
+    pub fn cover_art(&self) -> Option<&str> {
+        match self {
+            StarredAlbum::Folder(album) => album.cover_art.as_deref(),
+            StarredAlbum::Id3(album) => album.cover_art.as_deref(),
+        }
+    }
+
+    pub fn starred(&self) -> Option<&str> {
+        match self {
+            StarredAlbum::Folder(album) => album.starred.as_deref(),
+            StarredAlbum::Id3(album) => album.starred.as_deref(),
+        }
+    }
+}
+
+/// Starred artists, albums and songs regardless of which endpoint they came from.
+#[derive(Debug, Default)]
+pub struct StarredItems {
+    pub artists: Vec<StarredArtist>,
+    pub albums: Vec<StarredAlbum>,
+    pub songs: Vec<api::Child>,
+}
+
+impl StarredItems {
+    pub fn is_empty(&self) -> bool {
+        self.artists.is_empty() && self.albums.is_empty() && self.songs.is_empty()
+    }
+}
+
+impl From<api::Starred> for StarredItems {
+    fn from(starred: api::Starred) -> Self {
+        Self {
+            artists: starred
+                .artist
+                .into_iter()
+                .map(StarredArtist::Folder)
+                .collect(),
+            albums: starred
+                .album
+                .into_iter()
+                .map(StarredAlbum::Folder)
+                .collect(),
+            songs: starred.song,
+        }
+    }
+}
+
+impl From<api::Starred2> for StarredItems {
+    fn from(starred: api::Starred2) -> Self {
+        Self {
+            artists: starred.artist.into_iter().map(StarredArtist::Id3).collect(),
+            albums: starred.album.into_iter().map(StarredAlbum::Id3).collect(),
+            songs: starred.song,
+        }
+    }
+}
+
+impl Extend<StarredItems> for StarredItems {
+    fn extend<I: IntoIterator<Item = StarredItems>>(&mut self, iter: I) {
+        for items in iter {
+            self.artists.extend(items.artists);
+            self.albums.extend(items.albums);
+            self.songs.extend(items.songs);
+        }
+    }
+}
+
+impl api::Lyrics {
+    /// The lyrics are in `value`, which is empty if none were found.
+    pub async fn get<A: AsRef<str>, T: AsRef<str>>(
+        client: &Client,
+        artist: A,
+        title: T,
+    ) -> Result<Self> {
+        client
+            .get("getLyrics")
+            .query(&[("artist", artist.as_ref()), ("title", title.as_ref())])
+            .send()
+            .await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub content_type: Option<String>,
+    pub data: bytes::Bytes,
+}
+
+impl Image {
+    pub async fn cover_art<ID: AsRef<str>>(
+        client: &Client,
+        id: ID,
+        size: Option<u32>,
+    ) -> Result<Self> {
+        let req = client.get("getCoverArt").query(&[("id", id.as_ref())]);
+        let req = if let Some(size) = size {
+            req.query(&[("size", size)])
+        } else {
+            req
+        };
+        Self::fetch(req).await
+    }
+
+    pub async fn avatar<U: AsRef<str>>(client: &Client, username: U) -> Result<Self> {
+        let req = client
+            .get("getAvatar")
+            .query(&[("username", username.as_ref())]);
+        Self::fetch(req).await
+    }
+
+    async fn fetch(req: RequestBuilder<'_>) -> Result<Self> {
+        let (content_type, data) = req.send_binary().await?;
+        Ok(Self { content_type, data })
     }
 }
 
-impl api::Videos {
-    pub async fn get(client: &Client) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getVideos")
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionFormat {
+    Srt,
+    Vtt,
+}
+
+impl api::Captions {
+    /// Fetches the captions of a video as SRT or WebVTT. Without a format the server returns the
+    /// captions as they are stored.
+    pub async fn get<ID: AsRef<str>>(
+        client: &Client,
+        video_id: ID,
+        format: Option<CaptionFormat>,
+    ) -> Result<bytes::Bytes> {
+        let req = client
+            .get("getCaptions")
+            .query(&[("id", video_id.as_ref())]);
+        let req = if let Some(format) = format {
+            req.query(&[("format", format)])
+        } else {
+            req
+        };
+        let (_, captions) = req.send_binary().await?;
+        Ok(captions)
     }
 }
 
 impl api::VideoInfo {
-    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getVideoInfo")
-            .query(&[("id", id.as_ref())])
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+    pub fn audio_track<ID: AsRef<str>>(&self, id: ID) -> Option<&api::AudioTrack> {
+        self.audio_track
+            .iter()
+            .find(|audio_track| audio_track.id == id.as_ref())
     }
-}
 
-impl api::ArtistInfo {
-    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getArtistInfo")
-            .query(&[("id", id.as_ref())])
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+    /// Like `Child::hls_request` for this video. The audio track in the config must be one of
+    /// this video's.
+    pub fn hls_request(&self, client: &Client, config: &HlsConfig<'_>) -> Result<reqwest::Request> {
+        if let Some(id) = &config.audio_track {
+            if self.audio_track(id).is_none() {
+                return Err(Error::InvalidInput(format!(
+                    "video {} has no audio track {}",
+                    self.id, id
+                )));
+            }
+        }
+        hls_request(client, &self.id, config)
     }
 }
 
-impl api::ArtistInfo2 {
-    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getArtistInfo2")
-            .query(&[("id", id.as_ref())])
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
-    }
+/// An on-disk cache of cover art, keyed by id and size.
+///
+/// The cache is best effort: entries that can't be read or written are fetched from the server.
+#[derive(Debug, Clone)]
+pub struct CoverArtCache {
+    dir: std::path::PathBuf,
 }
 
-impl api::AlbumInfo {
-    pub async fn get<ID: AsRef<str>>(client: &Client, id: ID) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getAlbumInfo")
-            .query(&[("id", id.as_ref())])
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+impl CoverArtCache {
+    pub fn new<P: Into<std::path::PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
     }
 
-    pub async fn get_id3<ID: AsRef<str>>(
+    pub async fn get<ID: AsRef<str>>(
+        &self,
         client: &Client,
         id: ID,
-    ) -> Result<SubsonicResponse<Self>> {
-        Ok(client
-            .get("getAlbumInfo2")
-            .query(&[("id", id.as_ref())])
-            .send()
-            .await?
-            .json::<api::SubsonicResponse>()
-            .await?
-            .into())
+        size: Option<u32>,
+    ) -> Result<Image> {
+        let path = self.path(id.as_ref(), size);
+        if let Some(image) = tokio::fs::read(&path)
+            .await
+            .ok()
+            .and_then(|contents| Self::decode(contents.into()))
+        {
+            return Ok(image);
+        }
+
+        let image = Image::cover_art(client, id, size).await?;
+        let _ = self.store(&path, Self::encode(&image)).await;
+        Ok(image)
     }
-}
 
-impl api::Child {
-    pub async fn download(&self, client: &Client) -> Result<reqwest::Response> {
-        let request = self.download_request(client)?;
-        client.inner.execute(request).await
+    /// Writes to a temporary file first so readers never see a partially written entry.
+    async fn store(&self, path: &std::path::Path, contents: Vec<u8>) -> std::io::Result<()> {
+        use rand::{distributions::Alphanumeric, Rng};
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let suffix: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(char::from)
+            .collect();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(format!(".{}.tmp", suffix));
+        let temp = std::path::PathBuf::from(temp);
+        tokio::fs::write(&temp, contents).await?;
+        if let Err(error) = tokio::fs::rename(&temp, path).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(error);
+        }
+        Ok(())
     }
 
-    pub fn download_request(&self, client: &Client) -> Result<reqwest::Request> {
-        client
-            .get("download")
-            .query(&[("id", self.id.as_str())])
-            .build()
+    fn path(&self, id: &str, size: Option<u32>) -> std::path::PathBuf {
+        let id = md5::compute(id.as_bytes());
+        match size {
+            Some(size) => self.dir.join(format!("{:x}-{}", id, size)),
+            None => self.dir.join(format!("{:x}", id)),
+        }
+    }
+
+    /// Entries are the content type on the first line followed by the image data.
+    fn encode(image: &Image) -> Vec<u8> {
+        let content_type = image.content_type.as_deref().unwrap_or_default();
+        let mut contents = Vec::with_capacity(content_type.len() + 1 + image.data.len());
+        contents.extend_from_slice(content_type.as_bytes());
+        contents.push(b'\n');
+        contents.extend_from_slice(&image.data);
+        contents
+    }
+
+    fn decode(contents: bytes::Bytes) -> Option<Image> {
+        let newline = contents.iter().position(|byte| *byte == b'\n')?;
+        let content_type = std::str::from_utf8(&contents[..newline]).ok()?;
+        let content_type = (!content_type.is_empty()).then(|| content_type.to_owned());
+        let data = contents.slice(newline + 1..);
+        Some(Image { content_type, data })
     }
 }
 
@@ -415,6 +2811,33 @@ impl api::Child {
 mod tests {
     use super::*;
 
+    fn test_client() -> Client {
+        test_client_at("http://localhost/")
+    }
+
+    /// A client for a server started with `serve`.
+    fn test_client_at(url: &str) -> Client {
+        Client::new(url, "user".into(), "pass".into()).unwrap()
+    }
+
+    fn test_client_builder() -> ClientBuilder {
+        Client::builder("http://localhost/", "user".into(), "pass".into())
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_owned(), value.to_owned())
+    }
+
+    /// Every value of `key` in the query, in order.
+    fn query_values(request: &reqwest::Request, key: &str) -> Vec<String> {
+        request
+            .url()
+            .query_pairs()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+            .collect()
+    }
+
     #[derive(Debug, serde::Deserialize)]
     struct Config {
         user: String,
@@ -428,8 +2851,8 @@ mod tests {
         let config: Config = envy::prefixed("SUBSONIC_").from_env().unwrap();
 
         let client = Client::new(config.url, config.user, config.password).unwrap();
-        let success = client.ping().await.unwrap();
-        assert!(success);
+        client.ping().await.unwrap();
+        assert!(client.server_version().is_some());
     }
 
     #[tokio::test]
@@ -438,16 +2861,952 @@ mod tests {
         let config: Config = envy::prefixed("SUBSONIC_").from_env().unwrap();
         let client = Client::new(config.url, config.user, config.password).unwrap();
 
-        let response: SubsonicResponse<api::ArtistsID3> = client
-            .get("getArtists")
-            .send()
-            .await
+        let response = client.get("getArtists").send::<api::ArtistsID3>().await;
+        assert!(response.is_ok())
+    }
+
+    #[test]
+    fn get_with_params_query() {
+        let client = test_client();
+        let config = SearchConfig {
+            artist_count: Some(0),
+            music_folder_id: Some("1".into()),
+            ..Default::default()
+        };
+        let params = SearchParams {
+            query: "a b&c",
+            config: &config,
+        };
+        let request = client.get_with_params("search3", &params).build().unwrap();
+        assert_eq!(query_values(&request, "query"), ["a b&c"]);
+        assert_eq!(query_values(&request, "artistCount"), ["0"]);
+        assert_eq!(query_values(&request, "musicFolderId"), ["1"]);
+        assert!(query_values(&request, "albumCount").is_empty());
+    }
+
+    #[test]
+    fn get_with_params_repeated_keys() {
+        let client = test_client();
+        let config = UpdatePlaylistConfig {
+            public: Some(false),
+            song_id_to_add: vec!["10".into(), "11".into()],
+            song_index_to_remove: vec![0, 3],
+            ..Default::default()
+        };
+        let params = UpdatePlaylistParams {
+            playlist_id: "1",
+            config: &config,
+        };
+        let request = client
+            .get_with_params("updatePlaylist", &params)
+            .build()
+            .unwrap();
+        assert_eq!(query_values(&request, "playlistId"), ["1"]);
+        assert_eq!(query_values(&request, "public"), ["false"]);
+        assert_eq!(query_values(&request, "songIdToAdd"), ["10", "11"]);
+        assert_eq!(query_values(&request, "songIndexToRemove"), ["0", "3"]);
+        assert!(query_values(&request, "name").is_empty());
+    }
+
+    #[test]
+    fn album_list_params() {
+        let pairs = |list_type: &AlbumListType| {
+            let config = AlbumListConfig {
+                size: Some(20),
+                ..Default::default()
+            };
+            let mut pairs = query_pairs(&AlbumListParams {
+                list_type,
+                config: &config,
+            });
+            pairs.sort();
+            pairs
+        };
+
+        assert_eq!(
+            pairs(&AlbumListType::AlphabeticalByArtist),
+            [pair("size", "20"), pair("type", "alphabeticalByArtist")]
+        );
+        assert_eq!(
+            pairs(&AlbumListType::ByYear {
+                from: 2000,
+                to: 1990
+            }),
+            [
+                pair("fromYear", "2000"),
+                pair("size", "20"),
+                pair("toYear", "1990"),
+                pair("type", "byYear")
+            ]
+        );
+        assert_eq!(
+            pairs(&AlbumListType::ByGenre {
+                genre: "Rock".into()
+            }),
+            [
+                pair("genre", "Rock"),
+                pair("size", "20"),
+                pair("type", "byGenre")
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_response_type() {
+        let response = |json: &str| {
+            serde_json::from_str::<api::SubsonicResponse>(json)
+                .unwrap()
+                .subsonic_response
+                .content
+        };
+        let random_songs =
+            r#"{"subsonic-response":{"status":"ok","version":"1.16.1","randomSongs":{"song":[]}}}"#;
+        let songs_by_genre = r#"{"subsonic-response":{"status":"ok","version":"1.16.1","songsByGenre":{"song":[]}}}"#;
+
+        assert!(api::RandomSongs::try_from(response(random_songs)).is_ok());
+        assert!(matches!(
+            api::RandomSongs::try_from(response(songs_by_genre)),
+            Err(Error::UnexpectedResponse(Some(_)))
+        ));
+        assert!(api::SongsByGenre::try_from(response(songs_by_genre)).is_ok());
+    }
+
+    #[test]
+    fn user_rating_range() {
+        assert!(Rating::try_from(0).is_err());
+        assert_eq!(Rating::try_from(1).unwrap().get(), 1);
+        assert_eq!(Rating::try_from(5).unwrap().get(), 5);
+        assert!(Rating::try_from(6).is_err());
+    }
+
+    #[test]
+    fn jukebox_gain_range() {
+        assert!(JukeboxGain::try_from(-0.1).is_err());
+        assert_eq!(JukeboxGain::try_from(0.0).unwrap().get(), 0.0);
+        assert_eq!(JukeboxGain::try_from(1.0).unwrap().get(), 1.0);
+        assert!(JukeboxGain::try_from(1.5).is_err());
+        assert!(JukeboxGain::try_from(f32::NAN).is_err());
+    }
+
+    #[test]
+    fn user_params() {
+        let config = CreateUserConfig {
+            roles: UserRoles {
+                stream_role: Some(true),
+                admin_role: Some(false),
+                ..Default::default()
+            },
+            music_folder_id: vec!["1".into(), "2".into()],
+            ..Default::default()
+        };
+        let mut pairs = query_pairs(&CreateUserParams {
+            username: "new-hire",
+            password: EncodedPassword("sesame"),
+            email: "new-hire@example.com",
+            config: &config,
+        });
+        pairs.sort();
+        assert_eq!(
+            pairs,
+            [
+                pair("adminRole", "false"),
+                pair("email", "new-hire@example.com"),
+                pair("musicFolderId", "1"),
+                pair("musicFolderId", "2"),
+                pair("password", "enc:736573616d65"),
+                pair("streamRole", "true"),
+                pair("username", "new-hire"),
+            ]
+        );
+
+        let config = UpdateUserConfig {
+            password: Some("sesame".into()),
+            ..Default::default()
+        };
+        let pairs = query_pairs(&UpdateUserParams {
+            username: "new-hire",
+            config: &config,
+        });
+        assert!(pairs.contains(&pair("password", "enc:736573616d65")));
+        assert!(!pairs.iter().any(|(key, _)| key == "email"));
+    }
+
+    #[test]
+    fn play_queue_sync_staleness() {
+        let play_queue = |changed: &str| -> api::PlayQueue {
+            serde_json::from_value(serde_json::json!({
+                "username": "user",
+                "changed": changed,
+                "changedBy": "subsonic",
+            }))
             .unwrap()
-            .json::<api::SubsonicResponse>()
-            .await
+        };
+
+        let mut sync = PlayQueueSync::new();
+        assert!(sync.is_stale(&play_queue("2022-03-01T10:00:00.000Z")));
+
+        sync.changed = Some("2022-03-01T10:00:00.000Z".into());
+        assert!(!sync.is_stale(&play_queue("2022-03-01T10:00:00.000Z")));
+        assert!(sync.is_stale(&play_queue("2022-03-01T10:05:00.000Z")));
+    }
+
+    #[tokio::test]
+    async fn play_queue_saved_by_another_device_after_push() {
+        let url = serve(|target| {
+            static GETS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+            let queue = |id: &str, changed: &str, changed_by: &str| {
+                format!(
+                    r#"{{"subsonic-response":{{"status":"ok","version":"1.16.1","playQueue":{{"entry":[{{"id":"{}","isDir":false,"title":"t"}}],"username":"user","changed":"{}","changedBy":"{}"}}}}}}"#,
+                    id, changed, changed_by
+                )
+            };
+            let body = if target.starts_with("/rest/getPlayQueue") {
+                match GETS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                    0 => queue("1", "2022-03-01T10:00:00.000Z", "subsonic"),
+                    // Another device saved right after our save.
+                    _ => queue("2", "2022-03-01T10:05:00.000Z", "other"),
+                }
+            } else {
+                r#"{"subsonic-response":{"status":"ok","version":"1.16.1"}}"#.to_owned()
+            };
+            ("application/json", body)
+        })
+        .await;
+        let client = test_client_at(&url);
+        let mut sync = PlayQueueSync {
+            changed: Some("2022-03-01T10:00:00.000Z".into()),
+        };
+
+        let outcome = sync.push(&client, &["3"], None, None).await.unwrap();
+        assert!(matches!(
+            outcome,
+            PlayQueueSyncOutcome::Conflict(remote) if remote.changed_by == "other"
+        ));
+        assert_eq!(sync.changed.as_deref(), Some("2022-03-01T10:05:00.000Z"));
+    }
+
+    #[test]
+    fn play_queue_own_save() {
+        let client = test_client();
+        let play_queue = |ids: &[&str], changed_by: &str| -> api::PlayQueue {
+            let entry: Vec<_> = ids
+                .iter()
+                .map(|id| serde_json::json!({"id": id, "isDir": false, "title": "t"}))
+                .collect();
+            serde_json::from_value(serde_json::json!({
+                "entry": entry,
+                "username": "user",
+                "changed": "2022-03-01T10:00:00.000Z",
+                "changedBy": changed_by,
+            }))
+            .unwrap()
+        };
+
+        let ids = ["1", "2"];
+        assert!(PlayQueueSync::is_own_save(
+            &client,
+            &ids,
+            &play_queue(&ids, "subsonic")
+        ));
+        assert!(!PlayQueueSync::is_own_save(
+            &client,
+            &ids,
+            &play_queue(&ids, "other")
+        ));
+        assert!(!PlayQueueSync::is_own_save(
+            &client,
+            &ids,
+            &play_queue(&["1"], "subsonic")
+        ));
+    }
+
+    #[test]
+    fn share_params() {
+        let config = ShareConfig {
+            description: Some("review mix".into()),
+            expires: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_650_000_000)),
+        };
+        let mut pairs = query_pairs(&ShareParams {
+            id: vec!["1", "2"],
+            config: &config,
+        });
+        pairs.sort();
+        assert_eq!(
+            pairs,
+            [
+                pair("description", "review mix"),
+                pair("expires", "1650000000000"),
+                pair("id", "1"),
+                pair("id", "2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn internet_radio_station_urls() {
+        let params = InternetRadioStationParams::new(
+            None,
+            "Radio",
+            "http://radio.example.com/stream",
+            Some("http://radio.example.com"),
+        )
+        .unwrap();
+        let pairs = query_pairs(&params);
+        assert!(pairs.contains(&(
+            "streamUrl".to_string(),
+            "http://radio.example.com/stream".to_string()
+        )));
+        assert!(pairs.contains(&(
+            "homepageUrl".to_string(),
+            "http://radio.example.com/".to_string()
+        )));
+        assert!(!pairs.iter().any(|(key, _)| key == "id"));
+
+        assert!(InternetRadioStationParams::new(None, "Radio", "not a url", None::<&str>).is_err());
+        assert!(InternetRadioStationParams::new(
+            Some("1"),
+            "Radio",
+            "http://radio.example.com/stream",
+            Some("radio.example.com")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn chat_messages_newer_than() {
+        let messages = |times: &[i64]| -> api::ChatMessages {
+            let messages = times
+                .iter()
+                .map(|time| serde_json::json!({"username": "user", "time": time, "message": ""}))
+                .collect::<Vec<_>>();
+            serde_json::from_value(serde_json::json!({ "chatMessage": messages })).unwrap()
+        };
+        let times = |messages: Vec<api::ChatMessage>| {
+            messages
+                .into_iter()
+                .map(|message| message.time)
+                .collect::<Vec<_>>()
+        };
+
+        let mut since = None;
+        let new = api::ChatMessages::newer_than(messages(&[30, 10, 20]), &mut since);
+        assert_eq!(times(new), [10, 20, 30]);
+        assert_eq!(since, Some(30));
+
+        let new = api::ChatMessages::newer_than(messages(&[40, 30, 20]), &mut since);
+        assert_eq!(times(new), [40]);
+        assert_eq!(since, Some(40));
+
+        let new = api::ChatMessages::newer_than(messages(&[40]), &mut since);
+        assert!(new.is_empty());
+        assert_eq!(since, Some(40));
+    }
+
+    #[test]
+    fn starred_items() {
+        let starred: api::Starred = serde_json::from_value(serde_json::json!({
+            "artist": [{"id": "ar-1", "name": "Folder Artist"}],
+            "album": [{"id": "al-1", "isDir": true, "title": "Folder Album", "album": "Album"}],
+            "song": [{"id": "s-1", "isDir": false, "title": "Song"}],
+        }))
+        .unwrap();
+        let starred2: api::Starred2 = serde_json::from_value(serde_json::json!({
+            "artist": [{"id": "ar-2", "name": "ID3 Artist", "albumCount": 1}],
+            "album": [{
+                "id": "al-2",
+                "name": "ID3 Album",
+                "songCount": 1,
+                "duration": 60,
+                "created": "2022-03-01T10:00:00.000Z",
+            }],
+        }))
+        .unwrap();
+
+        let mut items = StarredItems::from(starred);
+        items.extend([StarredItems::from(starred2)]);
+        assert!(!items.is_empty());
+        assert_eq!(
+            items
+                .artists
+                .iter()
+                .map(StarredArtist::id)
+                .collect::<Vec<_>>(),
+            ["ar-1", "ar-2"]
+        );
+        assert_eq!(
+            items
+                .albums
+                .iter()
+                .map(StarredAlbum::name)
+                .collect::<Vec<_>>(),
+            ["Album", "ID3 Album"]
+        );
+        assert_eq!(items.songs.len(), 1);
+    }
+
+    #[test]
+    fn mixed_content() {
+        let lyrics: api::Lyrics = serde_json::from_str(
+            r#"{"artist":"Artist","title":"Song","value":"First line\nSecond line"}"#,
+        )
+        .unwrap();
+        assert_eq!(lyrics.value, "First line\nSecond line");
+
+        let lyrics: api::Lyrics = serde_json::from_str("{}").unwrap();
+        assert!(lyrics.value.is_empty());
+
+        let genre: api::Genre =
+            serde_json::from_str(r#"{"songCount":1,"albumCount":1,"value":"Rock"}"#).unwrap();
+        assert_eq!(genre.value, "Rock");
+    }
+
+    #[test]
+    fn video_hls_audio_track() {
+        let client = test_client();
+        let info: api::VideoInfo = serde_json::from_value(serde_json::json!({
+            "id": "v-1",
+            "audioTrack": [
+                {"id": "1", "languageCode": "en"},
+                {"id": "2", "languageCode": "de"},
+            ],
+        }))
+        .unwrap();
+        assert!(info.audio_track("3").is_none());
+
+        let config = HlsConfig {
+            audio_track: info
+                .audio_track("2")
+                .map(|audio_track| audio_track.id.as_str().into()),
+            ..Default::default()
+        };
+        let request = info.hls_request(&client, &config).unwrap();
+        assert_eq!(query_values(&request, "id"), ["v-1"]);
+        assert_eq!(query_values(&request, "audioTrack"), ["2"]);
+
+        let config = HlsConfig {
+            audio_track: Some("3".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            info.hls_request(&client, &config),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn scrobble_times() {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1000);
+        let entries = [
+            ScrobbleEntry {
+                id: "1".into(),
+                time: Some(time),
+            },
+            ScrobbleEntry {
+                id: "2".into(),
+                time: None,
+            },
+        ];
+        let params = ScrobbleParams::new(&entries, true);
+        assert_eq!(params.id, ["1", "2"]);
+        assert_eq!(params.time.len(), 2);
+        assert_eq!(params.time[0], 1000);
+        assert!(params.time[1] > 1000);
+
+        let params = ScrobbleParams::new(&entries[1..], false);
+        assert!(params.time.is_empty());
+    }
+
+    #[test]
+    fn stream_and_hls_requests() {
+        let client = test_client();
+        let child: api::Child =
+            serde_json::from_str(r#"{"id":"7","isDir":false,"title":"t"}"#).unwrap();
+
+        let request = child
+            .stream_request(
+                &client,
+                &StreamConfig {
+                    format: Some(StreamFormat::Raw),
+                    size: Some(VideoSize {
+                        width: 640,
+                        height: 480,
+                    }),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(request.url().path().ends_with("/rest/stream"));
+        assert_eq!(query_values(&request, "id"), ["7"]);
+        assert_eq!(query_values(&request, "format"), ["raw"]);
+        assert_eq!(query_values(&request, "size"), ["640x480"]);
+        assert!(query_values(&request, "maxBitRate").is_empty());
+
+        let request = child
+            .hls_request(
+                &client,
+                &HlsConfig {
+                    bit_rate: vec![
+                        HlsBitRate {
+                            bit_rate: 1000,
+                            size: Some(VideoSize {
+                                width: 480,
+                                height: 360,
+                            }),
+                        },
+                        HlsBitRate {
+                            bit_rate: 500,
+                            size: None,
+                        },
+                    ],
+                    audio_track: Some("2".into()),
+                },
+            )
+            .unwrap();
+        assert!(request.url().path().ends_with("/rest/hls.m3u8"));
+        assert_eq!(query_values(&request, "bitRate"), ["1000@480x360", "500"]);
+        assert_eq!(query_values(&request, "audioTrack"), ["2"]);
+    }
+
+    #[test]
+    fn cover_art_cache_entries() {
+        let cache = CoverArtCache::new("/tmp/covers");
+        assert_ne!(cache.path("al-1", None), cache.path("al-1", Some(64)));
+        assert_ne!(cache.path("al-1", Some(64)), cache.path("al-2", Some(64)));
+        assert!(cache.path("../al-1", None).starts_with("/tmp/covers"));
+
+        let image = Image {
+            content_type: Some("image/jpeg".into()),
+            data: bytes::Bytes::from_static(b"\xff\xd8\n\xff"),
+        };
+        let decoded = CoverArtCache::decode(CoverArtCache::encode(&image).into()).unwrap();
+        assert_eq!(decoded.content_type, image.content_type);
+        assert_eq!(decoded.data, image.data);
+
+        let image = Image {
+            content_type: None,
+            data: bytes::Bytes::new(),
+        };
+        let decoded = CoverArtCache::decode(CoverArtCache::encode(&image).into()).unwrap();
+        assert_eq!(decoded.content_type, None);
+        assert!(decoded.data.is_empty());
+    }
+
+    #[test]
+    fn empty_response() {
+        let client = test_client();
+        let ok = client.decode(br#"{"subsonic-response":{"status":"ok","version":"1.16.0"}}"#);
+        assert!(matches!(ok, Ok(None)));
+        assert_eq!(client.server_version().as_deref(), Some("1.16.0"));
+
+        let failed = client
+            .decode(br#"{"subsonic-response":{"status":"failed","version":"1.16.1","error":{"code":70,"message":"Not found"}}}"#)
             .unwrap()
-            .into();
-        assert!(response.result.is_ok())
+            .unwrap();
+        assert!(matches!(
+            Error::from(failed),
+            Error::Api(api::Error { code: 70, .. })
+        ));
+    }
+
+    #[test]
+    fn version_negotiation() {
+        assert_eq!(
+            Client::negotiate("1.12.0").unwrap(),
+            semver::Version::new(1, 12, 0)
+        );
+        assert_eq!(Client::negotiate("1.99.0").unwrap(), Client::API_VERSION);
+        assert!(matches!(
+            Client::negotiate("unknown"),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let mut client = test_client();
+        client.version = semver::Version::new(1, 12, 0);
+        let request = client.get("getIndexes").build().unwrap();
+        assert_eq!(query_values(&request, "v"), ["1.12.0"]);
+        assert_eq!(query_values(&request, "p"), ["pass"]);
+        assert!(matches!(
+            client.get("getAlbumInfo2").build(),
+            Err(Error::UnsupportedVersion { required, .. }) if required == semver::Version::new(1, 14, 0)
+        ));
+    }
+
+    #[test]
+    fn required_versions() {
+        let cases = [
+            ("ping", "1.0.0"),
+            ("getIndexes", "1.0.0"),
+            ("stream", "1.0.0"),
+            ("createUser", "1.1.0"),
+            ("changePassword", "1.1.0"),
+            ("getAlbumList", "1.2.0"),
+            ("getRandomSongs", "1.2.0"),
+            ("jukeboxControl", "1.2.0"),
+            ("getUser", "1.3.0"),
+            ("search2", "1.4.0"),
+            ("scrobble", "1.5.0"),
+            ("setRating", "1.6.0"),
+            ("getShares", "1.6.0"),
+            ("getAlbumList2", "1.8.0"),
+            ("search3", "1.8.0"),
+            ("hls.m3u8", "1.8.0"),
+            ("getSongsByGenre", "1.9.0"),
+            ("getBookmarks", "1.9.0"),
+            ("updateUser", "1.10.1"),
+            ("getArtistInfo2", "1.11.0"),
+            ("getSimilarSongs", "1.11.0"),
+            ("getPlayQueue", "1.12.0"),
+            ("savePlayQueue", "1.12.0"),
+            ("getTopSongs", "1.13.0"),
+            ("getNewestPodcasts", "1.13.0"),
+            ("getAlbumInfo2", "1.14.0"),
+            ("getCaptions", "1.14.0"),
+            ("startScan", "1.15.0"),
+            ("getScanStatus", "1.15.0"),
+            ("createInternetRadioStation", "1.16.0"),
+            ("deleteInternetRadioStation", "1.16.0"),
+        ];
+        for (endpoint, version) in cases {
+            assert_eq!(
+                Client::required_version(endpoint).to_string(),
+                version,
+                "{}",
+                endpoint
+            );
+        }
+    }
+
+    #[test]
+    fn client_builder() {
+        let client = test_client_builder()
+            .http_client(reqwest::Client::new())
+            .timeout(std::time::Duration::from_secs(5))
+            .client_name("my-app")
+            .user_agent("my-app/1.0")
+            .version(semver::Version::new(1, 13, 0))
+            .build()
+            .unwrap();
+        let request = client.get("ping").build().unwrap();
+        assert_eq!(query_values(&request, "c"), ["my-app"]);
+        assert_eq!(query_values(&request, "v"), ["1.13.0"]);
+        assert_eq!(request.timeout(), Some(&std::time::Duration::from_secs(5)));
+        assert_eq!(request.headers()[reqwest::header::USER_AGENT], "my-app/1.0");
+
+        assert!(matches!(
+            Client::builder("not a url", "user".into(), "pass".into()).build(),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn auth_methods() {
+        let query = |method: Option<AuthMethod>| {
+            let mut builder = test_client_builder();
+            if let Some(method) = method {
+                builder = builder.auth_method(method);
+            }
+            let client = builder.build().unwrap();
+            let request = client.get("ping").build().unwrap();
+            let mut pairs: Vec<(String, String)> =
+                request.url().query_pairs().into_owned().collect();
+            pairs.retain(|(key, _)| !["v", "c", "f"].contains(&key.as_str()));
+            pairs.sort();
+            pairs
+        };
+
+        let keys: Vec<_> = query(None).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["s", "t", "u"]);
+        assert_eq!(
+            query(Some(AuthMethod::Password)),
+            [pair("p", "pass"), pair("u", "user")]
+        );
+        assert_eq!(
+            query(Some(AuthMethod::EncodedPassword)),
+            [pair("p", "enc:70617373"), pair("u", "user")]
+        );
+        assert_eq!(
+            query(Some(AuthMethod::ApiKey("key".into()))),
+            [pair("apiKey", "key")]
+        );
+        assert_eq!(
+            format!("{:?}", AuthMethod::ApiKey("key".into())),
+            r#"ApiKey("<secret>")"#
+        );
+
+        let client = test_client();
+        assert_eq!(client.auth_method(), AuthMethod::Token);
+        assert!(client.auth.fall_back(&client.version));
+        assert_eq!(client.auth_method(), AuthMethod::EncodedPassword);
+        let request = client.get("ping").build().unwrap();
+        assert_eq!(query_values(&request, "p"), ["enc:70617373"]);
+        assert!(client.auth.fall_back(&client.version));
+        assert_eq!(client.auth_method(), AuthMethod::EncodedPassword);
+
+        let client = test_client_builder()
+            .auth_method(AuthMethod::Token)
+            .build()
+            .unwrap();
+        assert!(!client.auth.fall_back(&client.version));
+    }
+
+    #[test]
+    fn auth_strategies() {
+        let salts = |strategy| {
+            let client = test_client_builder()
+                .auth_strategy(strategy)
+                .build()
+                .unwrap();
+            let salt = || {
+                let request = client.get("ping").build().unwrap();
+                let salt = request.url().query_pairs().find(|(key, _)| key == "s");
+                salt.map(|(_, salt)| salt.into_owned())
+            };
+            (salt(), salt())
+        };
+
+        let (first, second) = salts(AuthStrategy::PerRequest);
+        assert_ne!(first, second);
+        let (first, second) = salts(AuthStrategy::Session(std::time::Duration::ZERO));
+        assert_ne!(first, second);
+        let (first, second) = salts(AuthStrategy::Session(std::time::Duration::from_secs(600)));
+        assert_eq!(first, second);
+        let (first, second) = salts(AuthStrategy::Precomputed);
+        assert!(first.is_some());
+        assert_eq!(first, second);
+
+        let client = test_client_builder()
+            .auth_strategy(AuthStrategy::Precomputed)
+            .build()
+            .unwrap();
+        let params = SearchParams {
+            query: "a&b",
+            config: &SearchConfig::default(),
+        };
+        let request = client.get_with_params("search3", &params).build().unwrap();
+        let (key, value) = request.url().query_pairs().next().unwrap();
+        assert_eq!(pair(&key, &value), pair("query", "a&b"));
+        assert_eq!(query_values(&request, "c"), ["subsonic"]);
+        assert!(client.auth.fall_back(&client.version));
+        let request = client.get("ping").build().unwrap();
+        assert_eq!(query_values(&request, "p"), ["enc:70617373"]);
+    }
+
+    /// Serves each connection with `respond(path and query)`, which returns the content type and
+    /// the body. `respond` runs on a blocking thread so it may wait for other requests. Returns the
+    /// base URL.
+    async fn serve(respond: fn(&str) -> (&'static str, String)) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = vec![0; 4096];
+                    let size = stream.read(&mut request).await.unwrap();
+                    let request = String::from_utf8_lossy(&request[..size]);
+                    let target = request.split(' ').nth(1).unwrap_or_default().to_owned();
+                    let (content_type, body) =
+                        tokio::task::spawn_blocking(move || respond(&target))
+                            .await
+                            .unwrap();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        content_type,
+                        body.len(),
+                        body
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        format!("http://{}/", address)
+    }
+
+    #[tokio::test]
+    async fn stream_reports_errors() {
+        use futures_util::TryStreamExt;
+
+        let url = serve(|target| {
+            if target.contains("id=7") {
+                ("audio/mpeg", "mp3".to_owned())
+            } else {
+                let error = r#"{"subsonic-response":{"status":"failed","version":"1.16.1","error":{"code":70,"message":"Song not found"}}}"#;
+                ("application/json", error.to_owned())
+            }
+        })
+        .await;
+        let client = test_client_at(&url);
+        let child = |id: &str| -> api::Child {
+            serde_json::from_value(serde_json::json!({"id": id, "isDir": false, "title": "t"}))
+                .unwrap()
+        };
+
+        let stream = child("7").stream(&client, &StreamConfig::default()).await;
+        let data: Vec<_> = stream.unwrap().try_collect().await.unwrap();
+        assert_eq!(data.concat(), b"mp3");
+        let error = child("8").stream(&client, &StreamConfig::default()).await;
+        assert!(matches!(error, Err(error) if error.is_not_found()));
+    }
+
+    #[tokio::test]
+    async fn cover_art_cache_store() {
+        let dir = std::env::temp_dir().join(format!("subsonic-cover-art-{}", std::process::id()));
+        let cache = CoverArtCache::new(&dir);
+        let path = cache.path("1", None);
+        cache
+            .store(&path, b"image/png\npng".to_vec())
+            .await
+            .unwrap();
+        cache
+            .store(&path, b"image/png\nnew".to_vec())
+            .await
+            .unwrap();
+
+        let mut entries = tokio::fs::read_dir(&dir).await.unwrap();
+        let mut names = vec![];
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name());
+        }
+        assert_eq!(names, [path.file_name().unwrap()]);
+        let contents = tokio::fs::read(&path).await.unwrap();
+        assert_eq!(CoverArtCache::decode(contents.into()).unwrap().data, "new");
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn token_fallback() {
+        let url = serve(|target| {
+            if target.contains("&t=") {
+                let error = r#"{"subsonic-response":{"status":"failed","version":"1.16.1","error":{"code":41}}}"#;
+                ("application/json", error.to_owned())
+            } else if !target.contains("&p=enc%3A70617373") {
+                let error = r#"{"subsonic-response":{"status":"failed","version":"1.16.1","error":{"code":40}}}"#;
+                ("application/json", error.to_owned())
+            } else if target.starts_with("/rest/ping") {
+                let ok = r#"{"subsonic-response":{"status":"ok","version":"1.16.1"}}"#;
+                ("application/json", ok.to_owned())
+            } else {
+                ("image/png", "png".to_owned())
+            }
+        })
+        .await;
+        let client = test_client_at(&url);
+        let image = Image::cover_art(&client, "1", None).await.unwrap();
+        assert_eq!(image.content_type.as_deref(), Some("image/png"));
+        assert_eq!(client.auth_method(), AuthMethod::EncodedPassword);
+        let client = test_client_at(&url);
+        client.ping().await.unwrap();
+        assert_eq!(client.auth_method(), AuthMethod::EncodedPassword);
+    }
+
+    #[tokio::test]
+    async fn scan_wait_timeout() {
+        let url = serve(|_| {
+            let scanning = r#"{"subsonic-response":{"status":"ok","version":"1.16.1","scanStatus":{"scanning":true,"count":10}}}"#;
+            ("application/json", scanning.to_owned())
+        })
+        .await;
+        let client = test_client_at(&url);
+        let config = ScanWaitConfig {
+            poll_interval: std::time::Duration::from_millis(10),
+            timeout: Some(std::time::Duration::from_millis(50)),
+        };
+        let result = api::ScanStatus::start_and_wait(&client, &config).await;
+        assert!(matches!(
+            result,
+            Err(Error::ScanTimeout(api::ScanStatus {
+                scanning: true,
+                count: Some(10)
+            }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn concurrent_token_fallback() {
+        let url = serve(|target| {
+            // Both requests are sent with a token before either learns that tokens are refused.
+            static BOTH_SENT: std::sync::Barrier = std::sync::Barrier::new(2);
+
+            if target.contains("&t=") {
+                BOTH_SENT.wait();
+                let error = r#"{"subsonic-response":{"status":"failed","version":"1.16.1","error":{"code":41}}}"#;
+                ("application/json", error.to_owned())
+            } else {
+                let ok = r#"{"subsonic-response":{"status":"ok","version":"1.16.1"}}"#;
+                ("application/json", ok.to_owned())
+            }
+        })
+        .await;
+        let client = test_client_at(&url);
+        let (first, second) = tokio::join!(client.ping(), client.ping());
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(client.auth_method(), AuthMethod::EncodedPassword);
+    }
+
+    #[test]
+    fn error_codes() {
+        for code in [0, 10, 20, 30, 40, 41, 50, 60, 70, 80] {
+            assert_eq!(i32::from(ErrorCode::from(code)), code);
+        }
+        assert_eq!(ErrorCode::from(80), ErrorCode::Other(80));
+
+        let error = |code| {
+            Error::Api(api::Error {
+                code,
+                message: None,
+            })
+        };
+        assert!(error(40).is_auth_error());
+        assert!(error(41).is_auth_error());
+        assert!(!error(50).is_auth_error());
+        assert!(!error(70).is_auth_error());
+        assert!(error(70).is_not_found());
+        assert_eq!(error(10).code(), Some(ErrorCode::MissingParameter));
+        assert_eq!(Error::InvalidInput(String::new()).code(), None);
+    }
+
+    #[test]
+    fn decode_malformed_content() {
+        let client = test_client();
+        let empty = client.decode(
+            br#"{"subsonic-response":{"status":"ok","version":"1.16.1","type":"navidrome","serverVersion":"0.50.0","openSubsonic":true}}"#,
+        );
+        assert!(matches!(empty, Ok(None)));
+
+        // The album is missing its required name.
+        let body =
+            br#"{"subsonic-response":{"status":"ok","version":"1.16.1","album":{"id":"1"}}}"#;
+        match client.decode(body) {
+            Err(Error::Decode { body, .. }) => assert!(body.contains(r#""album""#)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_error_keeps_body() {
+        let client = test_client();
+        let html = format!("<html>{}</html>", "x".repeat(1000));
+        match client.decode(html.as_bytes()) {
+            Err(Error::Decode { body, .. }) => {
+                assert!(body.starts_with("<html>"));
+                assert_eq!(body.len(), RequestBuilder::BODY_SNIPPET_SIZE);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(client.server_version(), None);
+    }
+
+    #[tokio::test]
+    async fn search3() {
+        dotenv::dotenv().unwrap();
+        let config: Config = envy::prefixed("SUBSONIC_").from_env().unwrap();
+        let client = Client::new(config.url, config.user, config.password).unwrap();
+
+        let response = api::SearchResult3::get(&client, "a", &SearchConfig::default()).await;
+        assert!(response.is_ok());
     }
 
     #[tokio::test]
@@ -456,22 +3815,15 @@ mod tests {
         let config: Config = envy::prefixed("SUBSONIC_").from_env().unwrap();
 
         let client = Client::new(config.url, config.user, config.password).unwrap();
-        let indexes = api::Indexes::get(&client).await.unwrap().result.unwrap();
+        let indexes = api::Indexes::get(&client).await.unwrap();
         let artist = api::ArtistWithAlbumsID3::get(&client, &indexes.index[0].artist[0].id)
             .await
-            .unwrap()
-            .result
             .unwrap();
         let album = api::AlbumWithSongsID3::get(&client, &artist.album[0].id)
             .await
-            .unwrap()
-            .result
             .unwrap();
         assert!(!album.song.is_empty());
-        let album_info = api::AlbumInfo::get(&client, &artist.album[0].id)
-            .await
-            .unwrap()
-            .result;
+        let album_info = api::AlbumInfo::get(&client, &artist.album[0].id).await;
         assert!(album_info.is_ok());
     }
 }